@@ -0,0 +1,466 @@
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::fs::write;
+use std::path::Path;
+use std::process::exit;
+use std::str::FromStr;
+
+use serde_json::{json, Value};
+
+use dtm_core::bulk::{BulkTagOperation, TagPosition};
+use dtm_core::caption::CaptionFormat;
+use dtm_core::dataset::{Dataset, DatasetImage};
+use dtm_core::huggingface::{CaptionColumns, HuggingFaceExportOptions, ImageTransfer};
+use dtm_core::kohya::{KohyaConfigOptions, DEFAULT_RESOLUTION};
+use dtm_core::pinned::pin_tags;
+use dtm_core::rules::TagRules;
+use dtm_core::settings::DatasetSettings;
+use dtm_core::stats::DEFAULT_CO_OCCURRENCE_TAGS;
+use dtm_core::webdataset::{ShardCaption, WebDatasetExportOptions, DEFAULT_SHARD_SAMPLES};
+
+const USAGE: &str = "\
+dtm edits the captions of an image dataset the same way the desktop app does.
+
+Usage:
+    dtm stats <dataset> [--top N] [--json]
+    dtm add-tag <dataset> <tag> [--position prepend|append|N] [--skip-existing] [--query Q] [--dry-run]
+    dtm remove-tag <dataset> <tag> [--query Q] [--dry-run]
+    dtm rename-tag <dataset> <old tag> <new tag> [--query Q] [--dry-run]
+    dtm lint <dataset> [--json]
+    dtm export <dataset> [--format json|jsonl] [--output FILE]
+    dtm export <dataset> --format huggingface --output FOLDER [--columns text|tags|both] [--hard-link]
+    dtm export <dataset> --format kohya [--output FILE] [--resolution N] [--keep-tokens N]
+    dtm export <dataset> --format webdataset --output FOLDER [--captions txt|json] [--shard-samples N] [--shard-bytes N]
+
+Every command also takes:
+    --max-depth N           how many levels of subfolders to read images from, the dataset's setting if not given
+    --caption-format F      comma, period, newline or sentence, detected from the captions if not given
+
+Like in the app, the dataset's normalization options and rules are applied when it is opened,
+and captions are written with the pinned tags in front. `--query` takes the query language
+of the app's search box and limits an edit to the images it matches.
+
+lint exits with 1 when it finds something, every command exits with 2 when it fails.";
+
+// options that don't take a value
+const SWITCHES: &[&str] = &["json", "skip-existing", "dry-run", "hard-link", "help"];
+
+// what every command accepts on top of its own options
+const COMMON_OPTIONS: &[&str] = &["max-depth", "caption-format", "help"];
+
+// The arguments after the command: positional arguments in order, `--name value` options and `--name` switches.
+// Everything after `--` is positional, for tags that start with dashes.
+struct Args {
+    positional: Vec<String>,
+    options: HashMap<String, String>,
+    switches: HashSet<String>,
+}
+
+impl Args {
+    fn parse(mut args: impl Iterator<Item = String>) -> Result<Args, String> {
+        let mut parsed = Args { positional: Vec::new(), options: HashMap::new(), switches: HashSet::new() };
+        let mut only_positional = false;
+
+        while let Some(arg) = args.next() {
+            if only_positional || !arg.starts_with("--") {
+                parsed.positional.push(arg);
+                continue;
+            }
+            if arg == "--" {
+                only_positional = true;
+                continue;
+            }
+
+            let (name, value) = match arg[2..].split_once('=') {
+                Some((name, value)) => (name.to_string(), Some(value.to_string())),
+                None => (arg[2..].to_string(), None)
+            };
+            if SWITCHES.contains(&name.as_str()) {
+                if value.is_some() {
+                    return Err(format!("--{} doesn't take a value", name));
+                }
+                parsed.switches.insert(name);
+                continue;
+            }
+
+            let value = match value.or_else(|| args.next()) {
+                Some(value) => value,
+                None => return Err(format!("--{} needs a value", name))
+            };
+            parsed.options.insert(name, value);
+        }
+
+        Ok(parsed)
+    }
+
+    // fails on options the command doesn't know, and on a wrong number of positional arguments
+    fn expect(&self, positional: &[&str], options: &[&str]) -> Result<(), String> {
+        let unknown = self.options.keys().chain(self.switches.iter())
+            .find(|name| !options.contains(&name.as_str()) && !COMMON_OPTIONS.contains(&name.as_str()));
+        if let Some(name) = unknown {
+            return Err(format!("Unknown option --{}", name));
+        }
+
+        if self.positional.len() < positional.len() {
+            return Err(format!("Missing <{}>", positional[self.positional.len()]));
+        }
+        if self.positional.len() > positional.len() {
+            return Err(format!("Unexpected argument '{}'", self.positional[positional.len()]));
+        }
+
+        Ok(())
+    }
+
+    fn option(&self, name: &str) -> Option<&str> {
+        self.options.get(name).map(|value| value.as_str())
+    }
+
+    fn parsed_option<T: FromStr>(&self, name: &str, default: T) -> Result<T, String> {
+        match self.option(name) {
+            Some(value) => value.parse().map_err(|_| format!("Invalid value '{}' for --{}", value, name)),
+            None => Ok(default)
+        }
+    }
+
+    fn switch(&self, name: &str) -> bool {
+        self.switches.contains(name)
+    }
+}
+
+// The dataset exactly as its caption files have it. Opening it doesn't write anything, images without a caption file have no tags.
+fn open(args: &Args) -> Result<Dataset, String> {
+    let path = Path::new(&args.positional[0]);
+    let max_depth = args.parsed_option("max-depth", DatasetSettings::load(path).max_depth())?;
+    let caption_format = match args.option("caption-format") {
+        Some(caption_format) => match serde_json::from_value::<CaptionFormat>(Value::String(caption_format.to_string())) {
+            Ok(caption_format) => Some(caption_format),
+            Err(_) => return Err(format!("Unknown caption format '{}'", caption_format))
+        },
+        None => None
+    };
+
+    Dataset::read_from_path(path, max_depth, caption_format).map_err(|err| err.to_string())
+}
+
+// The dataset the way the app opens it, with its tags normalized and its rules applied in memory.
+// Unlike the app, a rules file we can't read stops the command, a headless job shouldn't quietly skip the rules.
+fn prepare(dataset: Dataset) -> Result<Dataset, String> {
+    let (dataset, _) = dataset.normalize_tags(&dataset.settings.normalization, false);
+    let rules = match TagRules::load(Path::new(&dataset.path)) {
+        Ok(rules) => rules,
+        Err(err) => return Err(format!("Could not read the rules of the dataset: {}", err))
+    };
+    let (dataset, _) = dataset.apply_rules(&rules, false);
+    Ok(dataset)
+}
+
+// Where an image's caption is, for pointing the user at it. The images of a metadata file all share it, so its row is named too.
+fn caption_location(dataset: &Dataset, image: &DatasetImage) -> String {
+    if dataset.caption_source.is_shared() {
+        format!("{}[{}]", image.caption_file, image.relative_path)
+    } else {
+        image.caption_file.clone()
+    }
+}
+
+fn print_json(value: Result<Value, serde_json::Error>) -> Result<(), String> {
+    let contents = value.and_then(|value| serde_json::to_string_pretty(&value)).map_err(|err| err.to_string())?;
+    println!("{}", contents);
+    Ok(())
+}
+
+fn stats(args: &Args) -> Result<i32, String> {
+    args.expect(&["dataset"], &["top", "json"])?;
+    let top: Option<usize> = match args.option("top") {
+        Some(_) => Some(args.parsed_option("top", 0)?),
+        None => None
+    };
+    let dataset = prepare(open(args)?)?;
+    let mut stats = dataset.stats(top.unwrap_or(DEFAULT_CO_OCCURRENCE_TAGS));
+    if let Some(top) = top {
+        stats.tag_counts.truncate(top);
+    }
+
+    if args.switch("json") {
+        print_json(serde_json::to_value(&stats))?;
+        return Ok(0);
+    }
+
+    let tag_total: usize = stats.tags_per_image.iter().enumerate().map(|(tags, images)| tags * images).sum();
+    let tags_per_image = if stats.image_count == 0 { 0.0 } else { tag_total as f64 / stats.image_count as f64 };
+    println!("{}: {} images, {:.1} tags per image", dataset.name, stats.image_count, tags_per_image);
+    println!();
+    println!("{:>7}  {:>6}  tag", "images", "share");
+    for tag_count in stats.tag_counts.iter() {
+        println!("{:>7}  {:>5.1}%  {}", tag_count.count, tag_count.share * 100.0, tag_count.tag);
+    }
+
+    Ok(0)
+}
+
+fn parse_position(position: &str) -> Result<TagPosition, String> {
+    match position {
+        "prepend" => Ok(TagPosition::Prepend),
+        "append" => Ok(TagPosition::Append),
+        _ => match position.parse::<usize>() {
+            Ok(index) => Ok(TagPosition::Index(index)),
+            Err(_) => Err(format!("Invalid position '{}', expected prepend, append or an index", position))
+        }
+    }
+}
+
+// Applies the operation to the images matching `--query`, or to every image, and writes the captions that changed.
+// With `--dry-run` the new captions are only printed.
+fn edit_tags(args: &Args, operation: BulkTagOperation) -> Result<i32, String> {
+    let dataset = prepare(open(args)?)?;
+    // the images to edit by their relative path, like the core takes them
+    let relative_paths = match args.option("query") {
+        Some(query) => Some(dataset.query_images(query).map_err(|err| format!("Invalid query: {}", err))?),
+        None => None
+    };
+
+    if args.switch("dry-run") {
+        let mut changed = 0;
+        for image in dataset.data.iter() {
+            if let Some(relative_paths) = &relative_paths {
+                if !relative_paths.contains(&image.relative_path) {
+                    continue;
+                }
+            }
+
+            if let Some(tags) = operation.apply(&image.tags) {
+                let tags = pin_tags(&dataset.settings.pinned_tags, &tags);
+                println!("{}: {}", caption_location(&dataset, image), dataset.caption_format.join(&tags));
+                changed += 1;
+            }
+        }
+        println!("Would change the tags of {} images", changed);
+        return Ok(0);
+    }
+
+    let (_, changed) = dataset.bulk_edit_tags(relative_paths.as_deref(), &operation).map_err(|err| err.to_string())?;
+    println!("Changed the tags of {} images", changed.len());
+    Ok(0)
+}
+
+fn add_tag(args: &Args) -> Result<i32, String> {
+    args.expect(&["dataset", "tag"], &["position", "skip-existing", "query", "dry-run"])?;
+    let position = match args.option("position") {
+        Some(position) => parse_position(position)?,
+        None => TagPosition::Append
+    };
+    let operation = BulkTagOperation::Add { tag: args.positional[1].clone(), position, skip_existing: args.switch("skip-existing") };
+    edit_tags(args, operation)
+}
+
+fn remove_tag(args: &Args) -> Result<i32, String> {
+    args.expect(&["dataset", "tag"], &["query", "dry-run"])?;
+    let operation = BulkTagOperation::Remove { tag: args.positional[1].clone() };
+    edit_tags(args, operation)
+}
+
+fn rename_tag(args: &Args) -> Result<i32, String> {
+    args.expect(&["dataset", "old tag", "new tag"], &["query", "dry-run"])?;
+    let operation = BulkTagOperation::Replace { old_tag: args.positional[1].clone(), new_tag: args.positional[2].clone() };
+    edit_tags(args, operation)
+}
+
+// Everything about the captions on disk that opening the dataset in the app would change or complain about:
+// images without tags, tags an image has twice, pinned tags that aren't in front, and tags the normalization
+// options or the rules would rewrite. Exits with 1 if anything was found, so it can guard a training job.
+fn lint(args: &Args) -> Result<i32, String> {
+    args.expect(&["dataset"], &["json"])?;
+    let dataset = open(args)?;
+    // the core reports images by their relative path, the caption paths are easier to find
+    let paths: HashMap<&str, String> = dataset.data.iter().map(|image| (image.relative_path.as_str(), caption_location(&dataset, image))).collect();
+    let caption_of = |relative_path: &str| paths.get(relative_path).cloned().unwrap_or_else(|| relative_path.to_string());
+    let mut issues: Vec<(String, &str, String)> = Vec::new();
+
+    for image in dataset.data.iter() {
+        let caption = caption_location(&dataset, image);
+        if image.tags.iter().all(|tag| tag.is_empty()) {
+            issues.push((caption, "empty", "has no tags".to_string()));
+            continue;
+        }
+
+        let mut seen = HashSet::new();
+        let duplicates: Vec<&str> = image.tags.iter().filter(|tag| !seen.insert(tag.as_str())).map(|tag| tag.as_str()).collect();
+        if !duplicates.is_empty() {
+            issues.push((caption, "duplicate", format!("has these tags more than once: {}", duplicates.join(", "))));
+        }
+    }
+
+    for violation in dataset.pinned_tag_violations() {
+        let caption = caption_of(&violation.relative_path);
+        let message = if violation.missing.is_empty() {
+            format!("doesn't start with the pinned tags, starts with: {}", violation.leading.join(", "))
+        } else {
+            format!("is missing the pinned tags: {}", violation.missing.join(", "))
+        };
+        issues.push((caption, "pinned", message));
+    }
+
+    let (normalized, changes) = dataset.normalize_tags(&dataset.settings.normalization, false);
+    for change in changes {
+        let caption = caption_of(&change.relative_path);
+        issues.push((caption, "normalization", format!("would be normalized to: {}", dataset.caption_format.join(&change.after))));
+    }
+
+    let rules = match TagRules::load(Path::new(&dataset.path)) {
+        Ok(rules) => rules,
+        Err(err) => return Err(format!("Could not read the rules of the dataset: {}", err))
+    };
+    let (_, changes) = normalized.apply_rules(&rules, true);
+    for change in changes {
+        let caption = caption_of(&change.relative_path);
+        issues.push((caption, "rules", format!("would be changed by the rules to: {}", dataset.caption_format.join(&change.after))));
+    }
+
+    if args.switch("json") {
+        let issues: Vec<Value> = issues.iter().map(|(caption, kind, message)| json!({ "caption": caption, "kind": kind, "message": message })).collect();
+        print_json(Ok(Value::Array(issues)))?;
+    } else {
+        for (caption, _, message) in issues.iter() {
+            println!("{}: {}", caption, message);
+        }
+        println!("{} problems in {} images", issues.len(), dataset.data.len());
+    }
+
+    Ok(if issues.is_empty() { 0 } else { 1 })
+}
+
+// Copies the images with a `metadata.jsonl` into the `--output` folder, see `dtm_core::huggingface`.
+fn export_huggingface(args: &Args, dataset: &Dataset) -> Result<i32, String> {
+    let output = match args.option("output") {
+        Some(output) => output,
+        None => return Err("The huggingface format needs an --output folder".to_string())
+    };
+    let columns = match args.option("columns") {
+        Some(columns) => match serde_json::from_value::<CaptionColumns>(Value::String(columns.to_string())) {
+            Ok(columns) => columns,
+            Err(_) => return Err(format!("Invalid columns '{}', expected text, tags or both", columns))
+        },
+        None => CaptionColumns::Both
+    };
+    let transfer = if args.switch("hard-link") { ImageTransfer::HardLink } else { ImageTransfer::Copy };
+
+    let exported = dataset.export_huggingface(Path::new(output), &HuggingFaceExportOptions { columns, transfer }).map_err(|err| err.to_string())?;
+    eprintln!("Exported {} images to '{}'", exported, output);
+    Ok(0)
+}
+
+// Packs the images and their captions into tar shards in the `--output` folder, see `dtm_core::webdataset`.
+fn export_webdataset(args: &Args, dataset: &Dataset) -> Result<i32, String> {
+    let output = match args.option("output") {
+        Some(output) => output,
+        None => return Err("The webdataset format needs an --output folder".to_string())
+    };
+    let caption = match args.option("captions") {
+        Some(caption) => match serde_json::from_value::<ShardCaption>(Value::String(caption.to_string())) {
+            Ok(caption) => caption,
+            Err(_) => return Err(format!("Invalid captions '{}', expected txt or json", caption))
+        },
+        None => ShardCaption::Txt
+    };
+    let max_bytes = match args.option("shard-bytes") {
+        Some(_) => Some(args.parsed_option("shard-bytes", 0)?),
+        None => None
+    };
+    let options = WebDatasetExportOptions { caption, max_samples: args.parsed_option("shard-samples", DEFAULT_SHARD_SAMPLES)?, max_bytes };
+
+    let index = dataset.export_webdataset(Path::new(output), &options).map_err(|err| err.to_string())?;
+    eprintln!("Exported {} images in {} shards to '{}'", index.samples(), index.shardlist.len(), output);
+    Ok(0)
+}
+
+// The `dataset_config.toml` for Kohya's trainer, see `dtm_core::kohya`. It points the trainer at the caption files,
+// so it is made from the dataset exactly as it is on disk, without the normalization and the rules applied.
+fn kohya_config(args: &Args) -> Result<String, String> {
+    let dataset = open(args)?;
+    let options = KohyaConfigOptions {
+        resolution: args.parsed_option("resolution", DEFAULT_RESOLUTION)?,
+        keep_tokens: match args.option("keep-tokens") {
+            Some(_) => Some(args.parsed_option("keep-tokens", 0)?),
+            None => None
+        },
+    };
+    dataset.kohya_config(&options).map_err(|err| err.to_string())
+}
+
+// Writes the captions the way saving the dataset in the app would, to stdout or `--output`.
+// `json` is the dataset as the app sees it, `jsonl` has a line with the image path and its tags for every image.
+fn export(args: &Args) -> Result<i32, String> {
+    args.expect(&["dataset"], &["format", "output", "columns", "hard-link", "resolution", "keep-tokens", "captions", "shard-samples", "shard-bytes"])?;
+    if args.option("format") == Some("kohya") {
+        return write_export(args, &kohya_config(args)?, "the Kohya config");
+    }
+    let dataset = prepare(open(args)?)?;
+    let indices: Vec<usize> = (0..dataset.data.len()).collect();
+    let (dataset, _) = dataset.enforce_pinned_tags(&indices);
+
+    let contents = match args.option("format").unwrap_or("json") {
+        "json" => serde_json::to_string_pretty(&dataset).map_err(|err| err.to_string())? + "\n",
+        "jsonl" => dataset.data.iter()
+            .map(|image| json!({ "file_name": image.relative_path, "tags": image.tags, "text": dataset.caption_format.join(&image.tags) }).to_string() + "\n")
+            .collect(),
+        "huggingface" => return export_huggingface(args, &dataset),
+        "webdataset" => return export_webdataset(args, &dataset),
+        format => return Err(format!("Unknown export format '{}', expected json, jsonl, huggingface, webdataset or kohya", format))
+    };
+
+    write_export(args, &contents, &format!("{} images", dataset.data.len()))
+}
+
+// writes an export to `--output`, or to stdout without one
+fn write_export(args: &Args, contents: &str, exported: &str) -> Result<i32, String> {
+    match args.option("output") {
+        Some(output) => {
+            write(output, contents).map_err(|err| format!("Could not write '{}': {}", output, err))?;
+            eprintln!("Exported {} to '{}'", exported, output);
+        },
+        None => print!("{}", contents)
+    }
+
+    Ok(0)
+}
+
+fn main() {
+    let mut args = env::args().skip(1);
+    let command = match args.next() {
+        Some(command) => command,
+        None => {
+            eprintln!("{}", USAGE);
+            exit(2);
+        }
+    };
+
+    let args = match Args::parse(args) {
+        Ok(args) => args,
+        Err(err) => {
+            eprintln!("{}\n\n{}", err, USAGE);
+            exit(2);
+        }
+    };
+    if command == "help" || command == "--help" || args.switch("help") {
+        println!("{}", USAGE);
+        return;
+    }
+
+    let result = match command.as_str() {
+        "stats" => stats(&args),
+        "add-tag" => add_tag(&args),
+        "remove-tag" => remove_tag(&args),
+        "rename-tag" => rename_tag(&args),
+        "lint" => lint(&args),
+        "export" => export(&args),
+        _ => Err(format!("Unknown command '{}'", command))
+    };
+
+    match result {
+        Ok(code) => exit(code),
+        Err(err) => {
+            eprintln!("dtm: {}", err);
+            exit(2);
+        }
+    }
+}
@@ -0,0 +1,154 @@
+use std::fs::{copy, create_dir_all, read, read_dir, read_to_string, remove_dir_all};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{ Serialize, Deserialize };
+
+use super::file::write_atomic;
+use super::settings::DATASET_APP_DIR;
+
+const BACKUPS_DIR: &str = "backups";
+const MANIFEST_FILE: &str = "manifest.json";
+
+// How many snapshots we keep around for a dataset. Snapshots past either limit are removed after a new one is taken.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct BackupRetention {
+    pub max_count: Option<usize>,
+    pub max_age_days: Option<u64>,
+}
+
+impl Default for BackupRetention {
+    fn default() -> Self {
+        BackupRetention { max_count: Some(20), max_age_days: None }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct BackupFile {
+    // the `relative_path` of the image. snapshots taken before images were told apart by path have the image name,
+    // which is the same thing for images at the dataset root
+    #[serde(alias = "image_name")]
+    pub relative_path: String,
+    // path of the caption file relative to the dataset root, '/' separated
+    pub caption_path: String,
+    // whether the caption on disk differs from the one in the snapshot, filled in when listing
+    #[serde(default)]
+    pub changed: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct BackupSnapshot {
+    // also the name of the snapshot folder, milliseconds since the unix epoch
+    pub id: String,
+    // seconds since the unix epoch
+    pub created_at: u64,
+    pub files: Vec<BackupFile>,
+}
+
+pub fn backups_path(dataset_path: &Path) -> PathBuf {
+    dataset_path.join(DATASET_APP_DIR).join(BACKUPS_DIR)
+}
+
+fn caption_path_in(root: &Path, caption_path: &str) -> PathBuf {
+    caption_path.split('/').fold(root.to_path_buf(), |path, part| path.join(part))
+}
+
+// Copies the caption files that are about to be overwritten into `.dtm/backups/<id>/`, keeping their place in the dataset.
+// `files` holds the relative path of the image and the dataset relative caption path of every caption. Captions that don't exist yet are skipped,
+// and when none of them exist there is nothing to back up and no snapshot is taken.
+pub fn create_snapshot(dataset_path: &Path, files: Vec<(String, String)>) -> io::Result<Option<BackupSnapshot>> {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+    let id = now.as_millis().to_string();
+    let snapshot_path = backups_path(dataset_path).join(&id);
+
+    let files: Vec<(String, String)> = files.into_iter().filter(|(_, caption_path)| caption_path_in(dataset_path, caption_path).is_file()).collect();
+    if files.is_empty() {
+        return Ok(None);
+    }
+
+    let mut backup_files = Vec::new();
+    for (relative_path, caption_path) in files {
+        let source = caption_path_in(dataset_path, &caption_path);
+
+        let destination = caption_path_in(&snapshot_path, &caption_path);
+        if let Some(parent) = destination.parent() {
+            create_dir_all(parent)?;
+        }
+        copy(&source, &destination)?;
+
+        backup_files.push(BackupFile { relative_path, caption_path, changed: false });
+    }
+
+    let snapshot = BackupSnapshot { id, created_at: now.as_secs(), files: backup_files };
+
+    create_dir_all(&snapshot_path)?;
+    let manifest = serde_json::to_string_pretty(&snapshot).map_err(|err| io::Error::new(io::ErrorKind::Other, err))?;
+    write_atomic(&snapshot_path.join(MANIFEST_FILE), manifest.as_bytes())?;
+
+    Ok(Some(snapshot))
+}
+
+// Every snapshot of the dataset, newest first, with each file marked by whether it changed since the snapshot.
+pub fn list_snapshots(dataset_path: &Path) -> Vec<BackupSnapshot> {
+    let entries = match read_dir(backups_path(dataset_path)) {
+        Ok(entries) => entries,
+        Err(_) => return Vec::new()
+    };
+
+    let mut snapshots: Vec<BackupSnapshot> = entries.filter_map(|entry| {
+        let snapshot_path = entry.ok()?.path();
+        let manifest = read_to_string(snapshot_path.join(MANIFEST_FILE)).ok()?;
+        let mut snapshot: BackupSnapshot = serde_json::from_str(&manifest).ok()?;
+
+        for file in snapshot.files.iter_mut() {
+            let backup = read(caption_path_in(&snapshot_path, &file.caption_path)).ok();
+            let current = read(caption_path_in(dataset_path, &file.caption_path)).ok();
+            file.changed = backup != current;
+        }
+
+        Some(snapshot)
+    }).collect();
+
+    snapshots.sort_by(|a, b| b.id.cmp(&a.id));
+    snapshots
+}
+
+// Copies captions from a snapshot back into the dataset. With `relative_paths` only those images are restored.
+// Returns the files that were restored.
+pub fn restore_snapshot(dataset_path: &Path, id: &str, relative_paths: Option<&[String]>) -> io::Result<Vec<BackupFile>> {
+    let snapshot_path = backups_path(dataset_path).join(id);
+    let manifest = read_to_string(snapshot_path.join(MANIFEST_FILE))?;
+    let snapshot: BackupSnapshot = serde_json::from_str(&manifest).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+
+    let mut restored = Vec::new();
+    for file in snapshot.files {
+        if let Some(relative_paths) = relative_paths {
+            if !relative_paths.contains(&file.relative_path) {
+                continue;
+            }
+        }
+
+        let contents = read(caption_path_in(&snapshot_path, &file.caption_path))?;
+        write_atomic(&caption_path_in(dataset_path, &file.caption_path), &contents)?;
+        restored.push(file);
+    }
+
+    Ok(restored)
+}
+
+// removes the snapshots that fall outside the retention limits, the newest snapshot is always kept
+pub fn prune_snapshots(dataset_path: &Path, retention: &BackupRetention) {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+    let snapshots = list_snapshots(dataset_path);
+
+    for (index, snapshot) in snapshots.iter().enumerate().skip(1) {
+        let too_many = retention.max_count.map(|max_count| index >= max_count).unwrap_or(false);
+        let too_old = retention.max_age_days.map(|max_age_days| now.saturating_sub(snapshot.created_at) > max_age_days * 24 * 60 * 60).unwrap_or(false);
+
+        if too_many || too_old {
+            let _ = remove_dir_all(backups_path(dataset_path).join(&snapshot.id));
+        }
+    }
+}
@@ -0,0 +1,115 @@
+use serde::{ Serialize, Deserialize };
+
+use super::history::TagOperation;
+
+// Where an added tag goes. An index past the end of the tags appends.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum TagPosition {
+    Prepend,
+    Append,
+    Index(usize),
+}
+
+// One tag edit applied to many images at once.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum BulkTagOperation {
+    // with `skip_existing` images that already have the tag are left alone,
+    // otherwise the tag they have is moved to `position`
+    Add { tag: String, position: TagPosition, skip_existing: bool },
+    Remove { tag: String },
+    // an image that already has the new tag keeps it once, in the place it already had.
+    // the new tag is trimmed, renaming a tag to itself changes nothing
+    Replace { old_tag: String, new_tag: String },
+}
+
+impl BulkTagOperation {
+    pub fn history_operation(&self) -> TagOperation {
+        match self {
+            BulkTagOperation::Add { .. } => TagOperation::Add,
+            BulkTagOperation::Remove { .. } => TagOperation::Delete,
+            BulkTagOperation::Replace { .. } => TagOperation::Replace,
+        }
+    }
+
+    // the tags after the operation, or None if the operation doesn't change them
+    pub fn apply(&self, tags: &[String]) -> Option<Vec<String>> {
+        let new_tags = match self {
+            BulkTagOperation::Add { tag, position, skip_existing } => {
+                let tag = tag.trim();
+                if tag.is_empty() || (*skip_existing && tags.iter().any(|existing| existing == tag)) {
+                    return None;
+                }
+
+                let mut new_tags: Vec<String> = tags.iter().filter(|existing| *existing != tag).cloned().collect();
+                let index = match position {
+                    TagPosition::Prepend => 0,
+                    TagPosition::Append => new_tags.len(),
+                    TagPosition::Index(index) => (*index).min(new_tags.len()),
+                };
+                new_tags.insert(index, tag.to_string());
+                new_tags
+            },
+            BulkTagOperation::Remove { tag } => {
+                tags.iter().filter(|existing| *existing != tag).cloned().collect()
+            },
+            BulkTagOperation::Replace { old_tag, new_tag } => {
+                let new_tag = new_tag.trim();
+                if new_tag.is_empty() || new_tag == old_tag {
+                    return None;
+                }
+
+                let has_new_tag = tags.iter().any(|tag| tag == new_tag);
+                let mut new_tags: Vec<String> = Vec::with_capacity(tags.len());
+                for tag in tags {
+                    if tag != old_tag {
+                        new_tags.push(tag.clone());
+                    } else if !has_new_tag && !new_tags.iter().any(|existing| existing == new_tag) {
+                        new_tags.push(new_tag.to_string());
+                    }
+                }
+                new_tags
+            },
+        };
+
+        if new_tags == tags {
+            None
+        } else {
+            Some(new_tags)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tags(tags: &[&str]) -> Vec<String> {
+        tags.iter().map(|tag| tag.to_string()).collect()
+    }
+
+    fn replace(old_tag: &str, new_tag: &str) -> BulkTagOperation {
+        BulkTagOperation::Replace { old_tag: old_tag.to_string(), new_tag: new_tag.to_string() }
+    }
+
+    #[test]
+    fn replace_with_itself_keeps_the_tag() {
+        let image_tags = tags(&["1girl", "red hair", "smile"]);
+        assert_eq!(replace("red hair", "red hair").apply(&image_tags), None);
+        assert_eq!(replace("red hair", "  red hair ").apply(&image_tags), None);
+    }
+
+    #[test]
+    fn replace_with_empty_tag_changes_nothing() {
+        let image_tags = tags(&["1girl", "red hair"]);
+        assert_eq!(replace("red hair", " ").apply(&image_tags), None);
+    }
+
+    #[test]
+    fn replace_with_existing_tag_keeps_it_once() {
+        let image_tags = tags(&["red hair", "1girl", "long hair"]);
+        assert_eq!(replace("red hair", " long hair").apply(&image_tags), Some(tags(&["1girl", "long hair"])));
+        assert_eq!(replace("red hair", "blue hair").apply(&image_tags), Some(tags(&["blue hair", "1girl", "long hair"])));
+    }
+}
@@ -0,0 +1,78 @@
+use serde::{ Serialize, Deserialize };
+
+use super::weight::split_outside_emphasis;
+
+// How the tags of an image are laid out inside its caption file.
+// A dataset is read and written with a single format, so a caption read as
+// comma-separated is written back comma-separated.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum CaptionFormat {
+    // booru style, "1girl, red hair, st. louis"
+    Comma,
+    // "a cat. sitting on a mat"
+    Period,
+    // one tag per line
+    Newline,
+    // the whole caption is a single free-text sentence, nothing gets split
+    Sentence,
+}
+
+impl Default for CaptionFormat {
+    fn default() -> Self {
+        CaptionFormat::Comma
+    }
+}
+
+impl CaptionFormat {
+    // separators inside emphasis, like the period in "(masterpiece:1.2)", don't split tags
+    pub fn split(&self, contents: &str) -> Vec<String> {
+        let parts: Vec<&str> = match self {
+            CaptionFormat::Comma => split_outside_emphasis(contents, ','),
+            CaptionFormat::Period => split_outside_emphasis(contents, '.'),
+            CaptionFormat::Newline => contents.lines().collect(),
+            CaptionFormat::Sentence => vec![contents],
+        };
+
+        parts.iter().map(|s| s.trim().to_string()).filter(|s| !s.is_empty()).collect()
+    }
+
+    pub fn join(&self, tags: &[String]) -> String {
+        match self {
+            CaptionFormat::Comma => tags.join(", "),
+            CaptionFormat::Period => tags.join(". "),
+            CaptionFormat::Newline => tags.join("\n"),
+            CaptionFormat::Sentence => tags.join(" "),
+        }
+    }
+
+    // Guesses the format from the raw contents of a dataset's caption files.
+    // Whichever delimiter shows up in the most files wins; captions without any
+    // delimiter don't count towards anything. Falls back to the default format.
+    pub fn detect<'a>(captions: impl Iterator<Item = &'a str>) -> CaptionFormat {
+        let mut comma = 0;
+        let mut period = 0;
+        let mut newline = 0;
+
+        for caption in captions {
+            let caption = caption.trim();
+            if caption.contains('\n') {
+                newline += 1;
+            } else if caption.contains(',') {
+                comma += 1;
+            } else if caption.contains(". ") || caption.ends_with('.') {
+                period += 1;
+            }
+        }
+
+        if comma == 0 && period == 0 && newline == 0 {
+            CaptionFormat::default()
+        } else if comma >= period && comma >= newline {
+            CaptionFormat::Comma
+        } else if newline >= period {
+            CaptionFormat::Newline
+        } else {
+            CaptionFormat::Period
+        }
+    }
+}
@@ -0,0 +1,126 @@
+use std::collections::HashMap;
+use std::fs::{create_dir_all, read_to_string};
+use std::io;
+use std::path::{Path, PathBuf};
+
+use serde::{ Serialize, Deserialize };
+
+use super::csv;
+use super::dataset::Dataset;
+use super::file::write_atomic;
+use super::settings::DATASET_APP_DIR;
+use super::weight::WeightedTag;
+
+const CATEGORIES_FILE: &str = "categories.json";
+
+// The kind of thing a tag describes. Sorting by category puts tags in the order of the variants.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum TagCategory {
+    Character,
+    Artist,
+    Style,
+    General,
+    Quality,
+    Meta,
+}
+
+// tags nobody gave a category are general
+impl Default for TagCategory {
+    fn default() -> Self {
+        TagCategory::General
+    }
+}
+
+impl TagCategory {
+    // Reads a category as written in a tag dump, either its name or the number booru sites use for it.
+    // Booru sites have no style or quality categories, and copyright tags are general to us.
+    pub fn parse(category: &str) -> Option<TagCategory> {
+        match category.trim().to_lowercase().as_str() {
+            "character" | "4" => Some(TagCategory::Character),
+            "artist" | "1" => Some(TagCategory::Artist),
+            "style" => Some(TagCategory::Style),
+            "general" | "copyright" | "0" | "3" => Some(TagCategory::General),
+            "quality" => Some(TagCategory::Quality),
+            "meta" | "5" => Some(TagCategory::Meta),
+            _ => None
+        }
+    }
+}
+
+// The category of every tag that has one, stored in `.dtm/categories.json` inside the dataset.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct TagCategories {
+    pub categories: HashMap<String, TagCategory>,
+}
+
+impl TagCategories {
+    pub fn path(dataset_path: &Path) -> PathBuf {
+        dataset_path.join(DATASET_APP_DIR).join(CATEGORIES_FILE)
+    }
+
+    // like the settings, a dataset without categories (or with a file we can't make sense of) has none
+    pub fn load(dataset_path: &Path) -> TagCategories {
+        match read_to_string(TagCategories::path(dataset_path)) {
+            Ok(contents) => serde_json::from_str(&contents).unwrap_or_default(),
+            Err(_) => TagCategories::default()
+        }
+    }
+
+    pub fn save(&self, dataset_path: &Path) -> io::Result<()> {
+        let path = TagCategories::path(dataset_path);
+        if let Some(parent) = path.parent() {
+            create_dir_all(parent)?;
+        }
+
+        let contents = serde_json::to_string_pretty(self).map_err(|err| io::Error::new(io::ErrorKind::Other, err))?;
+        write_atomic(&path, contents.as_bytes())
+    }
+
+    // tag dumps write tags with underscores and without weights, so `(red hair:1.2)` finds the category of `red_hair`
+    pub fn get(&self, tag: &str) -> TagCategory {
+        if let Some(category) = self.categories.get(tag) {
+            return *category;
+        }
+
+        let text = WeightedTag::parse(tag).text;
+        self.categories.get(&text.replace(' ', "_")).copied().unwrap_or_default()
+    }
+
+    // Adds the categories from a CSV with the tag in the first column and its category in the second,
+    // overwriting the categories the tags already had. A header line, and lines whose category we don't know, are skipped.
+    // Returns how many tags got a category.
+    pub fn import_csv(&mut self, contents: &str) -> usize {
+        let mut imported = 0;
+        for fields in csv::parse(contents) {
+            if fields.len() < 2 || fields[0].trim().is_empty() {
+                continue;
+            }
+
+            if let Some(category) = TagCategory::parse(&fields[1]) {
+                self.categories.insert(fields[0].trim().to_string(), category);
+                imported += 1;
+            }
+        }
+        imported
+    }
+
+    // `None` takes the category away from the tag, which makes it general again
+    pub fn set(&mut self, tag: &str, category: Option<TagCategory>) {
+        match category {
+            Some(category) => self.categories.insert(tag.to_string(), category),
+            None => self.categories.remove(tag)
+        };
+    }
+}
+
+impl Dataset {
+    // the category of every tag in use in the dataset
+    pub fn tag_categories(&self) -> HashMap<String, TagCategory> {
+        self.data.iter()
+            .flat_map(|image| image.tags.iter())
+            .filter(|tag| !tag.is_empty())
+            .map(|tag| (tag.clone(), self.categories.get(tag)))
+            .collect()
+    }
+}
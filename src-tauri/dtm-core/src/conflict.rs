@@ -0,0 +1,57 @@
+use std::collections::hash_map::DefaultHasher;
+use std::fs::metadata;
+use std::hash::{Hash, Hasher};
+use std::path::Path;
+use std::time::SystemTime;
+
+use serde::{ Serialize, Deserialize };
+
+// only used to compare a caption against itself within a session, so it doesn't need to be stable across builds
+pub fn content_hash(contents: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    contents.hash(&mut hasher);
+    hasher.finish()
+}
+
+pub fn modified_time(path: &Path) -> Option<SystemTime> {
+    metadata(path).and_then(|metadata| metadata.modified()).ok()
+}
+
+// A caption file that changed on disk since we loaded (or last wrote) it, while the user has edits of their own.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct CaptionConflict {
+    pub relative_path: String,
+    pub caption_path: String,
+    // our tags
+    pub mine: Vec<String>,
+    // the tags in the caption file right now, empty if it was deleted
+    pub theirs: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ConflictResolution {
+    // our tags win, the caption file gets overwritten on the next save
+    KeepMine,
+    // the caption file wins, our edits are dropped
+    TakeTheirs,
+    // changes from both sides are kept, see `merge_tags`
+    Merge,
+}
+
+// Three-way merge of two tag lists against the tags they both started from.
+// A tag removed on one side stays removed, a tag added on either side is kept.
+// Our order comes first, tags only they added are appended in their order.
+pub fn merge_tags(base: &[String], mine: &[String], theirs: &[String]) -> Vec<String> {
+    let removed_by_them = |tag: &String| base.contains(tag) && !theirs.contains(tag);
+    let removed_by_me = |tag: &String| base.contains(tag) && !mine.contains(tag);
+
+    let mut merged: Vec<String> = mine.iter().filter(|tag| !removed_by_them(tag)).cloned().collect();
+    for tag in theirs {
+        if !merged.contains(tag) && !removed_by_me(tag) {
+            merged.push(tag.clone());
+        }
+    }
+
+    merged
+}
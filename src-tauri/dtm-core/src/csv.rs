@@ -0,0 +1,117 @@
+// Just enough CSV for the tag dumps people share for booru style datasets, and for `metadata.csv` caption files:
+//
+//   1girl,0,4114588,"sole_female,1girls"
+//
+// Fields are split on commas, a field in double quotes may contain commas and line breaks, and "" inside quotes is a quote.
+
+// the fields of one line, unquoted
+pub fn parse_line(line: &str) -> Vec<String> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            },
+            '"' => quoted = !quoted,
+            ',' if !quoted => fields.push(std::mem::take(&mut field)),
+            _ => field.push(c)
+        }
+    }
+    fields.push(field);
+
+    fields
+}
+
+// A record of a CSV file: its fields, and the text it was read from with its line ending, so it can be written back as it was.
+// Blank lines are records without fields.
+pub struct CsvRecord<'a> {
+    pub raw: &'a str,
+    pub fields: Vec<String>,
+}
+
+impl<'a> CsvRecord<'a> {
+    fn new(raw: &'a str) -> CsvRecord<'a> {
+        let text = raw.strip_suffix('\n').unwrap_or(raw);
+        let text = text.strip_suffix('\r').unwrap_or(text).replace("\r\n", "\n");
+        let fields = if text.trim().is_empty() { Vec::new() } else { parse_line(&text) };
+        CsvRecord { raw, fields }
+    }
+
+    // "\r\n" or "\n", nothing for a last line without one
+    pub fn line_ending(&self) -> &'a str {
+        if self.raw.ends_with("\r\n") {
+            "\r\n"
+        } else if self.raw.ends_with('\n') {
+            "\n"
+        } else {
+            ""
+        }
+    }
+}
+
+// Every record in order. A record goes on over the next line while a quote is open,
+// like a `metadata.csv` caption in the newline format.
+pub fn parse_records(contents: &str) -> Vec<CsvRecord<'_>> {
+    let mut records = Vec::new();
+    let mut start = 0;
+    let mut end = 0;
+    let mut quoted = false;
+
+    for line in contents.split_inclusive('\n') {
+        end += line.len();
+        // "" inside quotes closes and opens the quote again, so counting quotes is enough
+        quoted ^= line.matches('"').count() % 2 == 1;
+
+        if !quoted {
+            records.push(CsvRecord::new(&contents[start..end]));
+            start = end;
+        }
+    }
+    // a quote that is never closed runs to the end of the file
+    if start < contents.len() {
+        records.push(CsvRecord::new(&contents[start..]));
+    }
+
+    records
+}
+
+// the fields of every non-empty record
+pub fn parse(contents: &str) -> Vec<Vec<String>> {
+    parse_records(contents).into_iter()
+        .filter(|record| !record.fields.is_empty())
+        .map(|record| record.fields)
+        .collect()
+}
+
+// one line of fields, the other way around from `parse_line`
+pub fn write_line(fields: &[String]) -> String {
+    fields.iter().map(|field| {
+        if field.contains(',') || field.contains('"') || field.contains('\n') {
+            format!("\"{}\"", field.replace('"', "\"\""))
+        } else {
+            field.clone()
+        }
+    }).collect::<Vec<String>>().join(",")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quoted_line_breaks_round_trip() {
+        let rows = vec![
+            vec!["file_name".to_string(), "text".to_string()],
+            vec!["10_a/001.png".to_string(), "1girl\nred hair\n\"smile\"".to_string()],
+            vec!["5_b/001.png".to_string(), "solo, outdoors".to_string()],
+        ];
+        let contents: String = rows.iter().map(|row| write_line(row) + "\r\n").collect();
+
+        assert_eq!(parse(&contents), rows);
+    }
+}
@@ -0,0 +1,923 @@
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::fs::{read_dir, read_to_string, remove_file, write};
+use std::time::SystemTime;
+
+use serde::{ Serialize, Deserialize, Serializer };
+use serde::ser::SerializeStruct;
+
+use super::backup::{create_snapshot, prune_snapshots, restore_snapshot};
+use super::bulk::BulkTagOperation;
+use super::caption::CaptionFormat;
+use super::category::TagCategories;
+use super::conflict::{content_hash, merge_tags, modified_time, CaptionConflict, ConflictResolution};
+use super::file::{commit_staged_write, discard_staged_write, stage_write, write_atomic};
+use super::pinned::pin_tags;
+use super::settings::DatasetSettings;
+use super::source::{Caption, CaptionSource, Captions};
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct DatasetImage {
+    pub name: String,
+    pub path: String,
+    // path of the image relative to the dataset root, using '/' as the separator,
+    // so the frontend can group images by the folder they live in
+    #[serde(default)]
+    pub relative_path: String,
+    pub tags: Vec<String>,
+    // the file the caption is read from and written to (see `CaptionSource`), relative to the dataset root like
+    // `relative_path`, and as a full path. the images of a dataset with a metadata file all share it.
+    #[serde(skip)]
+    pub caption_file: String,
+    #[serde(skip)]
+    pub caption_path: PathBuf,
+    // the caption exactly as it was on disk, and the tags we parsed from it.
+    // we only ever rewrite a caption file when the tags no longer match, so
+    // files the user never touched keep their bytes (whitespace included).
+    #[serde(skip)]
+    pub original_caption: String,
+    #[serde(skip)]
+    pub original_tags: Vec<String>,
+    // what the caption file looked like when we last read or wrote it,
+    // so we notice when another tool changes it behind our back
+    #[serde(skip)]
+    pub original_hash: u64,
+    #[serde(skip)]
+    pub original_modified: Option<SystemTime>,
+}
+
+impl DatasetImage {
+    // a new image without any tags yet, its caption still has to be read
+    pub fn from_image_path(root: &Path, image_path: &Path, caption_source: CaptionSource) -> Result<DatasetImage, DatasetError> {
+        let name = match image_path.file_name() {
+            Some(name) => match name.to_str() {
+                Some(name) => name.to_string(),
+                None => {
+                    return Err(DatasetError::new(DatasetErrorType::Name, Some(image_path.to_string_lossy().to_string())));
+                }
+            },
+            None => {
+                return Err(DatasetError::new(DatasetErrorType::Name, Some(image_path.to_string_lossy().to_string())));
+            }
+        };
+
+        let relative_path = relative_path(root, image_path);
+        Ok(DatasetImage {
+            name,
+            path: image_path.to_string_lossy().to_string(),
+            caption_file: caption_source.caption_file(&relative_path),
+            caption_path: caption_source.caption_path(root, &relative_path),
+            relative_path,
+            tags: Vec::new(),
+            original_caption: String::new(),
+            original_tags: Vec::new(),
+            original_hash: 0,
+            original_modified: None
+        })
+    }
+
+    // an image is dirty when its tags differ from what is in its caption file
+    pub fn has_changed_tags(&self) -> bool {
+        self.tags != self.original_tags
+    }
+
+    // remembers `caption` as what is on disk right now, and our current tags as the tags parsed from it.
+    // `file_hash` is the `content_hash` of the whole caption file, which is more than the caption for a metadata file.
+    pub fn record_original(&mut self, caption: String, file_hash: u64) {
+        self.record_file(file_hash);
+        self.original_caption = caption;
+        self.original_tags = self.tags.clone();
+    }
+
+    // remembers what the caption file looks like right now, without the caption changing.
+    // for the images sharing a metadata file that was written for other images.
+    pub fn record_file(&mut self, file_hash: u64) {
+        self.original_hash = file_hash;
+        self.original_modified = modified_time(&self.caption_path);
+    }
+
+    // the frontend doesn't know about the caption file or any of the original fields, so they are carried over from our own copy of the image
+    pub fn copy_original_from(&mut self, image: &DatasetImage) {
+        self.caption_file = image.caption_file.clone();
+        self.caption_path = image.caption_path.clone();
+        self.original_caption = image.original_caption.clone();
+        self.original_tags = image.original_tags.clone();
+        self.original_hash = image.original_hash;
+        self.original_modified = image.original_modified;
+    }
+
+    // Whether the caption file changed on disk since we last read or wrote it.
+    // An unchanged modification time is trusted, otherwise the contents are compared.
+    // A caption file that still doesn't exist hasn't changed, one that was deleted has.
+    pub fn has_external_changes(&self) -> bool {
+        if self.original_modified.is_some() && modified_time(&self.caption_path) == self.original_modified {
+            return false;
+        }
+
+        match read_to_string(&self.caption_path) {
+            Ok(contents) => content_hash(&contents) != self.original_hash,
+            Err(_) => self.original_modified.is_some()
+        }
+    }
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct Dataset {
+    pub name: String,
+    pub path: String,
+    #[serde(default)]
+    pub caption_format: CaptionFormat,
+    // where the captions are read from and saved back to
+    #[serde(default)]
+    pub caption_source: CaptionSource,
+    #[serde(default)]
+    pub settings: DatasetSettings,
+    // every dataset operation works on a clone of the dataset, and a vocabulary imported from a tag dump is big
+    #[serde(skip)]
+    pub categories: Arc<TagCategories>,
+    pub data: Vec<DatasetImage>
+}
+
+// Written out by hand so the frontend gets `tag_categories`, the category of every tag in use, next to the images.
+impl Serialize for Dataset {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut dataset = serializer.serialize_struct("Dataset", 7)?;
+        dataset.serialize_field("name", &self.name)?;
+        dataset.serialize_field("path", &self.path)?;
+        dataset.serialize_field("caption_format", &self.caption_format)?;
+        dataset.serialize_field("caption_source", &self.caption_source)?;
+        dataset.serialize_field("settings", &self.settings)?;
+        dataset.serialize_field("tag_categories", &self.tag_categories())?;
+        dataset.serialize_field("data", &self.data)?;
+        dataset.end()
+    }
+}
+
+// how many levels of subfolders below the dataset root we read images from by default.
+// 0 means only the dataset root itself is read.
+pub const DEFAULT_MAX_DEPTH: usize = 8;
+
+#[derive(Debug)]
+#[allow(dead_code)]
+pub enum DatasetErrorType {
+    Name,
+    Path,
+    Read,
+    Write,
+    UnknownRead,
+    ShouldBeImpossible,
+    Backup,
+    // exporting into the dataset's own folder
+    Export,
+    // a Kohya config for a dataset the trainer can't read the captions of, and why
+    Kohya(String),
+    // replacing a tag with an empty one, which is removing it
+    EmptyTag,
+    // a tag weight that isn't a number above 0
+    Weight,
+    // these caption files were changed by something else since we read them, nothing was written
+    Conflict(Vec<String>),
+    // saving the dataset failed for these caption files, nothing was changed on disk
+    Save(Vec<String>),
+    // saving the dataset failed, and these caption files could not be put back the way they were
+    Rollback(Vec<String>),
+}
+
+#[derive(Debug)]
+pub struct DatasetError {
+    pub type_: DatasetErrorType,
+    pub path: Option<String>,
+}
+
+impl DatasetError {
+    pub fn new(type_: DatasetErrorType, path: Option<String>) -> DatasetError {
+        DatasetError { type_, path }
+    }
+}
+
+impl Error for DatasetError {}
+
+impl std::fmt::Display for DatasetError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let path = self.path.clone().unwrap_or("".to_string());
+        match self.type_ {
+            DatasetErrorType::Name => {
+                let msg = format!("Error reading name from path '{}'", path);
+                write!(f, "{msg}")
+            },
+            DatasetErrorType::Path => {
+                let msg = format!("Error reading path '{}'", path);
+                write!(f, "{msg}")
+            },
+            DatasetErrorType::Read => {
+                let msg = format!("Error reading dataset from path '{}'", path);
+                write!(f, "{msg}")
+            },
+            DatasetErrorType::Write => {
+                let msg = format!("Error writing file to path '{}'", path);
+                write!(f, "{msg}")
+            },
+            DatasetErrorType::UnknownRead => {
+                let msg = format!("Unknown error occurred while reading dataset from path '{}'", path);
+                write!(f, "{msg}")
+            },
+            DatasetErrorType::ShouldBeImpossible => {
+                write!(f, "An error occurred that should be impossible to occur")
+            },
+            DatasetErrorType::Backup => {
+                let msg = format!("Error backing up caption files to '{}', no changes were saved", path);
+                write!(f, "{msg}")
+            },
+            DatasetErrorType::Export => {
+                let msg = format!("Can't export the dataset to '{}', the export has to go outside the dataset folder", path);
+                write!(f, "{msg}")
+            },
+            DatasetErrorType::Kohya(ref reason) => {
+                let msg = format!("Can't write a Kohya config for '{}': {}", path, reason);
+                write!(f, "{msg}")
+            },
+            DatasetErrorType::EmptyTag => {
+                let msg = format!("Can't replace '{}' with an empty tag, remove it instead", path);
+                write!(f, "{msg}")
+            },
+            DatasetErrorType::Weight => {
+                let msg = format!("'{}' isn't a valid tag weight, it has to be a number above 0", path);
+                write!(f, "{msg}")
+            },
+            DatasetErrorType::Conflict(ref paths) => {
+                let msg = format!("These caption files were changed outside the app, no changes were saved:\n{}", paths.join("\n"));
+                write!(f, "{msg}")
+            },
+            DatasetErrorType::Save(ref paths) => {
+                let msg = format!("Error writing caption files, no changes were saved:\n{}", paths.join("\n"));
+                write!(f, "{msg}")
+            },
+            DatasetErrorType::Rollback(ref paths) => {
+                let msg = format!("Error writing caption files, and these files could not be restored:\n{}", paths.join("\n"));
+                write!(f, "{msg}")
+            },
+        }
+    }
+}
+
+// a caption file `write_images` wrote to a temp file, with the changed images in it
+struct StagedCaptionFile<'a> {
+    caption_path: &'a PathBuf,
+    indices: Vec<usize>,
+    temp_path: PathBuf,
+    contents: String,
+    // what the caption file had before, None if it didn't exist
+    previous: Option<String>,
+}
+
+impl Dataset {
+    pub fn has_unsaved_changes(&self) -> bool {
+        self.data.iter().any(|image| image.has_changed_tags())
+    }
+
+    pub fn dirty_images(&self) -> Vec<String> {
+        self.data.iter().filter(|image| image.has_changed_tags()).map(|image| image.relative_path.clone()).collect()
+    }
+
+    pub fn save_image_tags(&self) -> Result<Dataset, DatasetError> {
+        let indices: Vec<usize> = (0..self.data.len()).collect();
+        self.write_images(&indices)
+    }
+
+    // What the caption files of `images` have right now: the tags and the caption of each image, and the `content_hash`
+    // of its caption file. A caption file that doesn't exist, or a metadata file without a row for the image, has no tags.
+    pub fn read_captions(&self, images: &[&DatasetImage]) -> Result<Vec<(Vec<String>, String, u64)>, DatasetError> {
+        // a metadata file is read once for all of its images
+        let mut files: HashMap<&Path, (Option<Captions>, u64)> = HashMap::new();
+        let mut captions = Vec::with_capacity(images.len());
+
+        for image in images {
+            if !files.contains_key(image.caption_path.as_path()) {
+                let file = match read_to_string(&image.caption_path) {
+                    Ok(contents) => match self.caption_source.read(&contents) {
+                        Ok(file_captions) => (Some(file_captions), content_hash(&contents)),
+                        Err(err) => {
+                            return Err(DatasetError::new(DatasetErrorType::Read, Some(format!("{} ({})", image.caption_path.to_string_lossy(), err))));
+                        }
+                    },
+                    Err(_) => (None, content_hash(""))
+                };
+                files.insert(image.caption_path.as_path(), file);
+            }
+
+            let (file_captions, file_hash) = &files[image.caption_path.as_path()];
+            match file_captions.as_ref().and_then(|file_captions| file_captions.get(&image.relative_path)) {
+                Some(caption) => {
+                    let text = caption.to_text(self.caption_format);
+                    captions.push((caption.into_tags(self.caption_format), text, *file_hash));
+                },
+                None => captions.push((Vec::new(), String::new(), *file_hash))
+            }
+        }
+
+        Ok(captions)
+    }
+
+    // Whether the caption of any of `images` changed on disk since we last read or wrote it.
+    // In a metadata file only the rows of these images count, not the rest of the file.
+    pub fn captions_changed_externally(&self, images: &[&DatasetImage]) -> bool {
+        let changed: Vec<&DatasetImage> = images.iter().copied().filter(|image| image.has_external_changes()).collect();
+        if changed.is_empty() || !self.caption_source.is_shared() {
+            return !changed.is_empty();
+        }
+
+        match self.read_captions(&changed) {
+            Ok(captions) => captions.iter().zip(changed.iter()).any(|((_, caption, _), image)| caption != &image.original_caption),
+            Err(_) => true
+        }
+    }
+
+    // Writes the captions of the images at `indices`, returns the dataset with those captions as the new originals.
+    // Saving is all or nothing. Only the images whose tags changed get written, everything else stays untouched on disk.
+    // A metadata file holds the captions of many images, it is written once with the rows of all of its changed images.
+    // 0. if another tool changed any of the captions we are about to write, nothing is written and a conflict is reported.
+    // 1. every changed caption file is written to a temp file next to it. if any of those fail, the temp files are
+    //    removed and the failing paths are reported, without a single caption file having been touched.
+    // 2. the caption files that are about to be overwritten are copied into a backup snapshot (see `backup`).
+    // 3. the temp files are renamed over the caption files. if a rename fails, the caption files renamed so far get
+    //    their original contents back.
+    fn write_images(&self, indices: &[usize]) -> Result<Dataset, DatasetError> {
+        // every caption we write starts with the pinned tags. the images that had to be fixed count as changed from here on.
+        let (pinned, repinned) = self.enforce_pinned_tags(indices);
+        if !repinned.is_empty() {
+            return pinned.write_images(indices);
+        }
+
+        // the changed images by caption file
+        let mut files: Vec<(&PathBuf, Vec<usize>)> = Vec::new();
+        let mut positions: HashMap<&PathBuf, usize> = HashMap::new();
+        for index in indices.iter().copied().filter(|index| self.data[*index].has_changed_tags()) {
+            let caption_path = &self.data[index].caption_path;
+            match positions.get(caption_path) {
+                Some(position) => files[*position].1.push(index),
+                None => {
+                    positions.insert(caption_path, files.len());
+                    files.push((caption_path, vec![index]));
+                }
+            }
+        }
+
+        let conflicts: Vec<String> = files.iter()
+            .filter(|(_, indices)| {
+                let images: Vec<&DatasetImage> = indices.iter().map(|index| &self.data[*index]).collect();
+                self.captions_changed_externally(&images)
+            })
+            .map(|(caption_path, _)| caption_path.to_string_lossy().to_string())
+            .collect();
+        if !conflicts.is_empty() {
+            return Err(DatasetError::new(DatasetErrorType::Conflict(conflicts), None));
+        }
+
+        let mut dataset = self.clone();
+
+        let mut staged: Vec<StagedCaptionFile> = Vec::new();
+        let mut failed: Vec<String> = Vec::new();
+
+        for (caption_path, indices) in files {
+            // JSON and metadata files keep everything besides the captions, so the new contents start from what is there
+            let previous = read_to_string(caption_path).ok();
+            let captions: Vec<(&str, &[String])> = indices.iter()
+                .map(|index| (self.data[*index].relative_path.as_str(), self.data[*index].tags.as_slice()))
+                .collect();
+            let contents = match self.caption_source.write(previous.as_deref(), &captions, self.caption_format) {
+                Ok(contents) => contents,
+                Err(_) => {
+                    failed.push(caption_path.to_string_lossy().to_string());
+                    continue;
+                }
+            };
+
+            match stage_write(caption_path, contents.as_bytes()) {
+                Ok(temp_path) => staged.push(StagedCaptionFile { caption_path, indices, temp_path, contents, previous }),
+                Err(_) => failed.push(caption_path.to_string_lossy().to_string())
+            }
+        }
+
+        if !failed.is_empty() {
+            for file in &staged {
+                discard_staged_write(&file.temp_path);
+            }
+            return Err(DatasetError::new(DatasetErrorType::Save(failed), None));
+        }
+
+        // nothing changed, so there is nothing to back up or write
+        if staged.is_empty() {
+            return Ok(dataset);
+        }
+
+        // a metadata file goes into the backup under the first of its images, restoring it restores all of them
+        let backup_files = staged.iter().map(|file| {
+            let image = &self.data[file.indices[0]];
+            (image.relative_path.clone(), image.caption_file.clone())
+        }).collect();
+        if let Err(err) = self.back_up_captions(backup_files) {
+            for file in &staged {
+                discard_staged_write(&file.temp_path);
+            }
+            return Err(err);
+        }
+
+        let mut committed: Vec<(&PathBuf, Option<String>)> = Vec::new();
+        let mut staged = staged.into_iter();
+
+        for StagedCaptionFile { caption_path, indices, temp_path, contents, previous } in staged.by_ref() {
+            match commit_staged_write(&temp_path, caption_path) {
+                Ok(_) => {
+                    // what is on disk now is our new original
+                    let file_hash = content_hash(&contents);
+                    for index in indices {
+                        let caption = self.caption_format.join(&self.data[index].tags);
+                        dataset.data[index].record_original(caption, file_hash);
+                    }
+                    // the other images in a metadata file kept their rows, but the file they are in is new
+                    if self.caption_source.is_shared() {
+                        for image in dataset.data.iter_mut().filter(|image| &image.caption_path == caption_path) {
+                            image.record_file(file_hash);
+                        }
+                    }
+                    committed.push((caption_path, previous));
+                },
+                Err(_) => {
+                    failed.push(caption_path.to_string_lossy().to_string());
+                    break;
+                }
+            }
+        }
+
+        if failed.is_empty() {
+            return Ok(dataset);
+        }
+
+        for file in staged {
+            discard_staged_write(&file.temp_path);
+        }
+
+        // put back what was on disk before we started
+        let mut not_restored: Vec<String> = Vec::new();
+        for (caption_path, previous) in committed {
+            let restored = match previous {
+                Some(previous) => write_atomic(caption_path, previous.as_bytes()),
+                None => remove_file(caption_path)
+            };
+            if restored.is_err() {
+                not_restored.push(caption_path.to_string_lossy().to_string());
+            }
+        }
+
+        if not_restored.is_empty() {
+            Err(DatasetError::new(DatasetErrorType::Save(failed), None))
+        } else {
+            Err(DatasetError::new(DatasetErrorType::Rollback(not_restored), None))
+        }
+    }
+
+    // Puts the caption files from a backup snapshot back on disk, and picks up the restored tags.
+    // With `relative_paths` only those images are restored. Returns the new dataset and the restored images.
+    pub fn restore_backup(&self, id: &str, relative_paths: Option<&[String]>) -> Result<(Dataset, Vec<DatasetImage>), DatasetError> {
+        let restored = match restore_snapshot(Path::new(&self.path), id, relative_paths) {
+            Ok(restored) => restored,
+            Err(_) => {
+                return Err(DatasetError::new(DatasetErrorType::Write, Some(self.path.clone())));
+            }
+        };
+
+        // a restored metadata file brings back the captions of every image in it
+        let restored_files: HashSet<&str> = restored.iter().map(|file| file.caption_path.as_str()).collect();
+        let indices: Vec<usize> = (0..self.data.len()).filter(|index| restored_files.contains(self.data[*index].caption_file.as_str())).collect();
+        let images: Vec<&DatasetImage> = indices.iter().map(|index| &self.data[*index]).collect();
+        let captions = self.read_captions(&images)?;
+
+        let mut dataset = self.clone();
+        let mut restored_images = Vec::new();
+
+        for (index, (tags, caption, file_hash)) in indices.into_iter().zip(captions) {
+            let image = &mut dataset.data[index];
+            image.tags = tags;
+            image.record_original(caption, file_hash);
+            restored_images.push(image.clone());
+        }
+
+        Ok((dataset, restored_images))
+    }
+
+    // Every image with unsaved edits whose caption was also changed on disk.
+    // `image` stands in for our copy of that image, for edits that haven't made it into the dataset yet.
+    pub fn caption_conflicts(&self, image: Option<&DatasetImage>) -> Vec<CaptionConflict> {
+        self.data.iter().filter_map(|dataset_image| {
+            let mine = match image {
+                Some(image) if image.relative_path == dataset_image.relative_path => &image.tags,
+                _ => &dataset_image.tags
+            };
+            if mine == &dataset_image.original_tags || !self.captions_changed_externally(&[dataset_image]) {
+                return None;
+            }
+
+            let theirs = self.read_captions(&[dataset_image]).ok()
+                .and_then(|captions| captions.into_iter().next())
+                .map(|(tags, _, _)| tags)
+                .unwrap_or_default();
+            Some(CaptionConflict {
+                relative_path: dataset_image.relative_path.clone(),
+                caption_path: dataset_image.caption_path.to_string_lossy().to_string(),
+                mine: mine.clone(),
+                theirs,
+            })
+        }).collect()
+    }
+
+    // Settles a conflict between our tags (`mine`, or the tags in the dataset) and the caption on disk.
+    // Only our state changes, the caption on disk becomes the new original so the next write goes through.
+    // An image that was deleted while it had unsaved edits is dropped from the dataset when theirs is taken.
+    pub fn resolve_conflict(&self, relative_path: &str, resolution: ConflictResolution, mine: Option<Vec<String>>) -> Result<Dataset, DatasetError> {
+        let mut dataset = self.clone();
+
+        let image = match dataset.data.iter_mut().find(|image| image.relative_path == relative_path) {
+            Some(image) => image,
+            None => return Err(DatasetError::new(DatasetErrorType::ShouldBeImpossible, None))
+        };
+
+        // a caption file that was deleted counts as an empty one
+        let (theirs, theirs_caption, file_hash) = match self.read_captions(&[image]) {
+            Ok(mut captions) => captions.remove(0),
+            Err(err) => return Err(err)
+        };
+        let mine = mine.unwrap_or_else(|| image.tags.clone());
+
+        let tags = match resolution {
+            ConflictResolution::KeepMine => mine,
+            ConflictResolution::TakeTheirs => theirs.clone(),
+            ConflictResolution::Merge => merge_tags(&image.original_tags, &mine, &theirs),
+        };
+
+        let removed = resolution == ConflictResolution::TakeTheirs && !Path::new(&image.path).is_file();
+
+        image.tags = theirs;
+        image.record_original(theirs_caption, file_hash);
+        image.tags = tags;
+
+        if removed {
+            dataset.data.retain(|image| image.relative_path != relative_path);
+        }
+
+        Ok(dataset)
+    }
+
+    // Renames a tag in every image that has it, and writes only the captions of those images.
+    // An image that already has the new tag ends up with it once, in the place it already had.
+    // Returns the new dataset and the relative paths of the images that changed.
+    pub fn rename_tag(&self, old_tag: &str, new_tag: &str) -> Result<(Dataset, Vec<String>), DatasetError> {
+        let operation = BulkTagOperation::Replace { old_tag: old_tag.to_string(), new_tag: new_tag.to_string() };
+        self.bulk_edit_tags(None, &operation)
+    }
+
+    // Applies one tag operation to every image in `relative_paths` (or every image in the dataset) as a single batch,
+    // and writes only the captions of the images that changed.
+    // Returns the new dataset and the relative paths of the images that changed.
+    pub fn bulk_edit_tags(&self, relative_paths: Option<&[String]>, operation: &BulkTagOperation) -> Result<(Dataset, Vec<String>), DatasetError> {
+        if let BulkTagOperation::Replace { old_tag, new_tag } = operation {
+            if new_tag.trim().is_empty() {
+                return Err(DatasetError::new(DatasetErrorType::EmptyTag, Some(old_tag.clone())));
+            }
+        }
+        let relative_paths: Option<HashSet<&String>> = relative_paths.map(|relative_paths| relative_paths.iter().collect());
+
+        let mut dataset = self.clone();
+        let mut changed: Vec<usize> = Vec::new();
+
+        for (index, image) in dataset.data.iter_mut().enumerate() {
+            if let Some(relative_paths) = &relative_paths {
+                if !relative_paths.contains(&image.relative_path) {
+                    continue;
+                }
+            }
+
+            if let Some(tags) = operation.apply(&image.tags) {
+                image.tags = tags;
+                changed.push(index);
+            }
+        }
+
+        let dataset = dataset.write_images(&changed)?;
+        let changed_paths = changed.iter().map(|index| dataset.data[*index].relative_path.clone()).collect();
+
+        Ok((dataset, changed_paths))
+    }
+
+    // Removes a tag from an image and writes its caption, through `update_image` so a caption that was
+    // changed on disk is reported as a conflict instead of being overwritten.
+    pub fn delete_image_tag(&self, tag: String, relative_path: String) -> Result<Dataset, DatasetError> {
+        let mut image = match self.data.iter().find(|image| image.relative_path == relative_path) {
+            Some(image) => image.clone(),
+            // We shouldn't possibly be able to get here, but we'll handle it just in case
+            None => return Err(DatasetError::new(DatasetErrorType::ShouldBeImpossible, None))
+        };
+
+        match image.tags.iter().position(|image_tag| image_tag == &tag) {
+            Some(index) => {
+                image.tags.remove(index);
+            },
+            None => return Err(DatasetError::new(DatasetErrorType::ShouldBeImpossible, None))
+        }
+
+        self.update_image(&mut image)
+    }
+
+    pub fn update_image(&self, image: &mut DatasetImage) -> Result<Dataset, DatasetError> {
+        let mut dataset_data = self.data.clone();
+
+        let mut image_index = None;
+        for (index, dataset_image) in dataset_data.iter().enumerate() {
+            if dataset_image.relative_path == image.relative_path {
+                image_index = Some(index);
+                break;
+            }
+        }
+
+        if let Some(index) = image_index {
+            image.copy_original_from(&dataset_data[index]);
+            image.tags = pin_tags(&self.settings.pinned_tags, &image.tags);
+
+            if !image.has_changed_tags() {
+                return Ok(self.clone());
+            }
+
+            if self.captions_changed_externally(&[image]) {
+                return Err(DatasetError::new(DatasetErrorType::Conflict(vec![image.caption_path.to_string_lossy().to_string()]), None));
+            }
+
+            // like saving, the caption file goes into a snapshot before it is overwritten
+            self.back_up_captions(vec![(image.relative_path.clone(), image.caption_file.clone())])?;
+
+            match self.write_image_tags_for_file(image) {
+                Ok(file_hash) => {
+                    // now we can update our local state
+                    self.record_shared_file(&mut dataset_data, &image.caption_path, file_hash);
+                    dataset_data[index] = image.clone();
+                },
+                Err(err) => {
+                    return Err(err);
+                }
+            }
+        } else {
+            // We shouldn't possibly be able to get here, but we'll handle it just in case
+            return Err(DatasetError::new(DatasetErrorType::ShouldBeImpossible, None));
+        }
+
+        Ok(Dataset {
+            name: self.name.clone(),
+            path: self.path.clone(),
+            caption_format: self.caption_format,
+            caption_source: self.caption_source,
+            settings: self.settings.clone(),
+            categories: self.categories.clone(),
+            data: dataset_data
+        })
+    }
+
+    // Takes a backup snapshot of the caption files (by image relative path and caption path) that are about to be
+    // overwritten, and drops the snapshots past the dataset's retention.
+    fn back_up_captions(&self, files: Vec<(String, String)>) -> Result<(), DatasetError> {
+        let dataset_path = Path::new(&self.path);
+        match create_snapshot(dataset_path, files) {
+            Ok(Some(_)) => {
+                prune_snapshots(dataset_path, &self.settings.backup_retention);
+                Ok(())
+            },
+            Ok(None) => Ok(()),
+            Err(_) => Err(DatasetError::new(DatasetErrorType::Backup, Some(dataset_path.to_string_lossy().to_string())))
+        }
+    }
+
+    // Writes the caption of `image` to its caption file, returns the `content_hash` of the file as written.
+    pub fn write_image_tags_for_file(&self, image: &mut DatasetImage) -> Result<u64, DatasetError> {
+        let caption_path = image.caption_path.clone();
+        let write_error = || DatasetError::new(DatasetErrorType::Write, Some(caption_path.to_string_lossy().to_string()));
+
+        // JSON and metadata files keep everything besides the caption
+        let current = read_to_string(&caption_path).ok();
+        let contents = match self.caption_source.write(current.as_deref(), &[(image.relative_path.as_str(), image.tags.as_slice())], self.caption_format) {
+            Ok(contents) => contents,
+            Err(_) => return Err(write_error())
+        };
+
+        let write_result = write_atomic(&caption_path, contents.as_bytes());
+        match write_result {
+            Ok(_) => {},
+            Err(_) => {
+                return Err(write_error());
+            }
+        }
+
+        // what is on disk now is our new original
+        let file_hash = content_hash(&contents);
+        image.record_original(self.caption_format.join(&image.tags), file_hash);
+
+        Ok(file_hash)
+    }
+
+    // the images sharing a metadata file that was just written keep their rows, but the file they are in is new
+    fn record_shared_file(&self, data: &mut [DatasetImage], caption_path: &Path, file_hash: u64) {
+        if self.caption_source.is_shared() {
+            for image in data.iter_mut().filter(|image| image.caption_path == caption_path) {
+                image.record_file(file_hash);
+            }
+        }
+    }
+
+    
+    // TODO: custom error types, will allow us to handle showing error dialogs to the user
+    // if no caption format is given, it is detected from the caption files in the dataset
+    pub fn from_path(path: &Path, max_depth: usize, caption_format: Option<CaptionFormat>) -> Result<Dataset, DatasetError> {
+        Dataset::load(path, max_depth, caption_format, true)
+    }
+
+    // Like `from_path`, but without writing anything: missing caption files stay missing, and their images have no tags.
+    // For looking at a dataset, a caption file is still created when its image is saved.
+    pub fn read_from_path(path: &Path, max_depth: usize, caption_format: Option<CaptionFormat>) -> Result<Dataset, DatasetError> {
+        Dataset::load(path, max_depth, caption_format, false)
+    }
+
+    fn load(path: &Path, max_depth: usize, caption_format: Option<CaptionFormat>, create_missing: bool) -> Result<Dataset, DatasetError> {
+        let dataset_name = match path.file_name() {
+            Some(name) => match name.to_str() {
+                Some(name) => name.to_string(),
+                None => return Err(DatasetError::new(DatasetErrorType::Name, Some(path.to_string_lossy().to_string())))
+            },
+            None => return Err(DatasetError::new(DatasetErrorType::Name, Some(path.to_string_lossy().to_string())))
+        };
+
+        let dataset_path = path.to_string_lossy().to_string();
+
+        // we want to iterate through the files in the directory, and in every subfolder up to max_depth levels deep
+        // we want to create a DatasetImage for each image file in the directory if that image file.
+        // if the image has a caption in its caption file (see `CaptionSource`), we want to read the tags from it and add them to the DatasetImage as its tags
+        // if the image does not have a caption yet, its tags are empty, and a missing `.txt` or `.caption` file is created
+        // we want to add the DatasetImage to the Dataset's data vector
+        // we want to return the Dataset
+
+        let image_paths = find_image_files(path, max_depth)?;
+        let settings = DatasetSettings::load(path);
+        let caption_source = match settings.caption_source {
+            Some(caption_source) => caption_source,
+            None => CaptionSource::detect(path, &image_paths)
+        };
+        let create_missing = create_missing && caption_source.creates_missing();
+        let read_error = |caption_path: &Path, err: String| DatasetError::new(DatasetErrorType::Read, Some(format!("{} ({})", caption_path.to_string_lossy(), err)));
+
+        // a metadata file is read once, a missing one has no captions yet.
+        // the file hash goes with it, every image in it records the same one.
+        let metadata = if caption_source.is_shared() {
+            let caption_path = caption_source.caption_path(path, "");
+            let contents = read_to_string(&caption_path).unwrap_or_default();
+            match caption_source.read(&contents) {
+                Ok(captions) => Some((captions, content_hash(&contents))),
+                Err(err) => return Err(read_error(&caption_path, err))
+            }
+        } else {
+            None
+        };
+
+        // we hold on to the caption of every image until we know which format to split them with.
+        // images without a caption yet have none.
+        let mut dataset_captions: Vec<(DatasetImage, Option<Caption>, u64)> = Vec::new();
+
+        for dataimage_path in image_paths {
+            let image = DatasetImage::from_image_path(path, &dataimage_path, caption_source)?;
+
+            if let Some((captions, file_hash)) = &metadata {
+                let caption = captions.get(&image.relative_path);
+                dataset_captions.push((image, caption, *file_hash));
+                continue;
+            }
+
+            // we want to check if the caption file exists
+            // if it does, we want to read the caption from the file
+            // if it does not, we want to create the file for the sources that always have one
+            let caption = match read_to_string(&image.caption_path) {
+                Ok(contents) => match caption_source.read(&contents) {
+                    Ok(captions) => (captions.get(&image.relative_path), content_hash(&contents)),
+                    Err(err) => return Err(read_error(&image.caption_path, err))
+                },
+                Err(_) => {
+                    if create_missing && write(&image.caption_path, "").is_err() {
+                        return Err(DatasetError::new(DatasetErrorType::Write, Some(image.caption_path.to_string_lossy().to_string())));
+                    }
+                    (None, content_hash(""))
+                }
+            };
+
+            dataset_captions.push((image, caption.0, caption.1));
+        }
+
+        let caption_format = match caption_format {
+            Some(caption_format) => caption_format,
+            None => CaptionFormat::detect(dataset_captions.iter().filter_map(|(_, caption, _)| match caption {
+                Some(Caption::Text(text)) => Some(text.as_str()),
+                _ => None
+            }))
+        };
+
+        let dataset_data = dataset_captions.into_iter().map(|(mut image, caption, file_hash)| {
+            let original_caption = caption.as_ref().map(|caption| caption.to_text(caption_format)).unwrap_or_default();
+            // a caption file we just created starts out with one empty tag, like it always did
+            image.tags = match caption {
+                Some(caption) => caption.into_tags(caption_format),
+                None if create_missing => vec!["".to_string()],
+                None => Vec::new()
+            };
+            image.record_original(original_caption, file_hash);
+            image
+        }).collect();
+
+        Ok(Dataset {
+            name: dataset_name,
+            path: dataset_path,
+            caption_format,
+            caption_source,
+            settings,
+            categories: Arc::new(TagCategories::load(path)),
+            data: dataset_data
+        })
+    }
+}
+
+// Every image file in `root` and in its subfolders up to `max_depth` levels deep.
+// Images in a folder come before the images in its subfolders, so images stay grouped by folder.
+pub fn find_image_files(root: &Path, max_depth: usize) -> Result<Vec<PathBuf>, DatasetError> {
+    let mut image_files = Vec::new();
+    find_image_files_in(root, 0, max_depth, &mut image_files)?;
+    Ok(image_files)
+}
+
+fn find_image_files_in(dir: &Path, depth: usize, max_depth: usize, image_files: &mut Vec<PathBuf>) -> Result<(), DatasetError> {
+    let entries = match read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => {
+            return Err(DatasetError::new(DatasetErrorType::Read, Some(dir.to_string_lossy().to_string())));
+        }
+    };
+
+    let mut subdirs = Vec::new();
+
+    for entry in entries {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(_) => {
+                return Err(DatasetError::new(DatasetErrorType::Read, Some(dir.to_string_lossy().to_string())));
+            }
+        };
+
+        let entry_path = entry.path();
+
+        // we don't follow symlinked folders, so a link pointing back up the tree can't loop forever.
+        // hidden folders (.git, our own .dtm folder, etc.) are never part of the dataset.
+        let is_dir = entry.file_type().map(|file_type| file_type.is_dir()).unwrap_or(false);
+        if is_dir {
+            let is_hidden = entry.file_name().to_string_lossy().starts_with('.');
+            if depth < max_depth && !is_hidden {
+                subdirs.push(entry_path);
+            }
+            continue;
+        }
+
+        if entry_path.is_file() && is_image_file(&entry_path) {
+            image_files.push(entry_path);
+        }
+    }
+
+    subdirs.sort();
+    for subdir in subdirs {
+        find_image_files_in(&subdir, depth + 1, max_depth, image_files)?;
+    }
+
+    Ok(())
+}
+
+// builds the '/' separated path of `path` relative to `root`, falling back to the file name
+fn relative_path(root: &Path, path: &Path) -> String {
+    match path.strip_prefix(root) {
+        Ok(relative) => relative.components().map(|component| component.as_os_str().to_string_lossy().to_string()).collect::<Vec<String>>().join("/"),
+        Err(_) => path.file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_default()
+    }
+}
+
+fn is_image_file(path: &Path) -> bool {
+    match path.extension() {
+        Some(extension) => {
+            let ext = match extension.to_str() {
+                Some(extension) => extension,
+                None => return false
+            };
+
+            matches!(ext, "jpg" | "jpeg" | "png" | "gif" | "bmp" | "tiff" | "tif" | "webp")
+        },
+        None => false
+    }
+}
\ No newline at end of file
@@ -0,0 +1,64 @@
+use std::fs::{remove_file, rename, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+// the temp file sits next to the file it replaces, since a rename is only atomic within the same filesystem
+fn temp_path_for(path: &Path) -> PathBuf {
+    let file_name = path.file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_default();
+    path.with_file_name(format!(".{}.dtm-tmp", file_name))
+}
+
+// Writes the contents to a temp file next to `path` and fsyncs it, without touching `path` itself.
+// Returns the temp path, which is handed to `commit_staged_write` or `discard_staged_write` afterwards.
+pub fn stage_write(path: &Path, contents: &[u8]) -> io::Result<PathBuf> {
+    let temp_path = temp_path_for(path);
+
+    let result = File::create(&temp_path).and_then(|mut file| {
+        file.write_all(contents)?;
+        file.sync_all()
+    });
+
+    match result {
+        Ok(_) => Ok(temp_path),
+        Err(err) => {
+            let _ = remove_file(&temp_path);
+            Err(err)
+        }
+    }
+}
+
+// Moves a staged temp file over `path`. Either the old or the new contents end up on disk, never a mix.
+pub fn commit_staged_write(temp_path: &Path, path: &Path) -> io::Result<()> {
+    if let Err(err) = rename(temp_path, path) {
+        let _ = remove_file(temp_path);
+        return Err(err);
+    }
+
+    // make the rename itself durable, windows has no way of syncing a directory
+    #[cfg(unix)]
+    if let Some(parent) = path.parent() {
+        let _ = File::open(parent).and_then(|dir| dir.sync_all());
+    }
+
+    Ok(())
+}
+
+pub fn discard_staged_write(temp_path: &Path) {
+    let _ = remove_file(temp_path);
+}
+
+// a crash or a full disk halfway through leaves the previous file intact
+pub fn write_atomic(path: &Path, contents: &[u8]) -> io::Result<()> {
+    let temp_path = stage_write(path, contents)?;
+    commit_staged_write(&temp_path, path)
+}
+
+// `path` with symlinks and `..` resolved, for as much of it as exists already
+pub fn resolve(path: &Path) -> PathBuf {
+    for ancestor in path.ancestors() {
+        if let Ok(resolved) = ancestor.canonicalize() {
+            return resolved.join(path.strip_prefix(ancestor).unwrap_or(path));
+        }
+    }
+    path.to_path_buf()
+}
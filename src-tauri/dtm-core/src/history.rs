@@ -0,0 +1,133 @@
+use serde::{ Serialize, Deserialize };
+
+use super::dataset::{Dataset, DatasetImage};
+
+// how many tag operations we keep around to undo
+pub const MAX_HISTORY_LENGTH: usize = 200;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum TagOperation {
+    Add,
+    Delete,
+    Reorder,
+    Replace,
+}
+
+impl TagOperation {
+    // works out which operation turned `before` into `after`
+    pub fn between(before: &[String], after: &[String]) -> TagOperation {
+        if after.len() == before.len() + 1 && before.iter().all(|tag| after.contains(tag)) {
+            return TagOperation::Add;
+        }
+        if before.len() == after.len() + 1 && after.iter().all(|tag| before.contains(tag)) {
+            return TagOperation::Delete;
+        }
+
+        let mut sorted_before = before.to_vec();
+        let mut sorted_after = after.to_vec();
+        sorted_before.sort();
+        sorted_after.sort();
+        if sorted_before == sorted_after {
+            return TagOperation::Reorder;
+        }
+
+        TagOperation::Replace
+    }
+}
+
+// the tags of a single image before and after an operation
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ImageTagChange {
+    // the image's `relative_path`, its name alone can be in more than one folder
+    pub relative_path: String,
+    pub before: Vec<String>,
+    pub after: Vec<String>,
+}
+
+// one undoable step. bulk operations touch many images, but are still undone as one step.
+#[derive(Clone, Debug)]
+pub struct HistoryEntry {
+    pub operation: TagOperation,
+    pub changes: Vec<ImageTagChange>,
+}
+
+impl HistoryEntry {
+    // compares the tags of every image in both datasets, returns None if nothing changed.
+    // tag operations never add, remove or move images, so both datasets line up image by image.
+    pub fn between(operation: TagOperation, before: &Dataset, after: &Dataset) -> Option<HistoryEntry> {
+        let changes: Vec<ImageTagChange> = before.data.iter().zip(after.data.iter()).filter_map(|(before_image, after_image)| {
+            if before_image.relative_path != after_image.relative_path || before_image.tags == after_image.tags {
+                return None;
+            }
+
+            Some(ImageTagChange {
+                relative_path: before_image.relative_path.clone(),
+                before: before_image.tags.clone(),
+                after: after_image.tags.clone(),
+            })
+        }).collect();
+
+        if changes.is_empty() {
+            None
+        } else {
+            Some(HistoryEntry { operation, changes })
+        }
+    }
+}
+
+#[derive(Default, Debug)]
+pub struct TagHistory {
+    undo_stack: Vec<HistoryEntry>,
+    redo_stack: Vec<HistoryEntry>,
+}
+
+impl TagHistory {
+    pub fn record(&mut self, entry: HistoryEntry) {
+        self.undo_stack.push(entry);
+        if self.undo_stack.len() > MAX_HISTORY_LENGTH {
+            self.undo_stack.remove(0);
+        }
+        // a new edit makes whatever we undid before unreachable
+        self.redo_stack.clear();
+    }
+
+    pub fn clear(&mut self) {
+        self.undo_stack.clear();
+        self.redo_stack.clear();
+    }
+
+    // puts the tags from before the last operation back on the dataset, returns the operation and the images that changed
+    pub fn undo(&mut self, dataset: &mut Dataset) -> Option<(TagOperation, Vec<DatasetImage>)> {
+        let entry = self.undo_stack.pop()?;
+        let images = apply_changes(dataset, &entry.changes, |change| &change.before);
+        let operation = entry.operation;
+        self.redo_stack.push(entry);
+        Some((operation, images))
+    }
+
+    // re-applies the last undone operation, returns the operation and the images that changed
+    pub fn redo(&mut self, dataset: &mut Dataset) -> Option<(TagOperation, Vec<DatasetImage>)> {
+        let entry = self.redo_stack.pop()?;
+        let images = apply_changes(dataset, &entry.changes, |change| &change.after);
+        let operation = entry.operation;
+        self.undo_stack.push(entry);
+        Some((operation, images))
+    }
+}
+
+fn apply_changes<F>(dataset: &mut Dataset, changes: &[ImageTagChange], tags_of: F) -> Vec<DatasetImage>
+where
+    F: Fn(&ImageTagChange) -> &Vec<String>
+{
+    let mut images = Vec::new();
+
+    for change in changes {
+        if let Some(image) = dataset.data.iter_mut().find(|image| image.relative_path == change.relative_path) {
+            image.tags = tags_of(change).clone();
+            images.push(image.clone());
+        }
+    }
+
+    images
+}
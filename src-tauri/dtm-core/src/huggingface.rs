@@ -0,0 +1,157 @@
+// Hugging Face's imagefolder layout: the images, and a `metadata.jsonl` with a line per image next to them.
+//
+//   {"file_name": "10_subject/1.png", "text": "1girl, red hair", "tags": ["1girl", "red hair"]}
+//
+// `file_name` is relative to the metadata file, so the images keep the folders they have in the dataset.
+
+use std::fs::{copy, create_dir_all, hard_link, read_to_string, remove_file};
+use std::io;
+use std::path::{Component, Path};
+
+use serde::{ Serialize, Deserialize };
+use serde_json::{Map, Value};
+
+use super::caption::CaptionFormat;
+use super::dataset::{Dataset, DatasetError, DatasetErrorType};
+use super::file::{resolve, write_atomic};
+use super::pinned::pin_tags;
+use super::source::object_caption;
+
+pub const METADATA_FILE: &str = "metadata.jsonl";
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ImageTransfer {
+    Copy,
+    // takes no extra space, falls back to copying where a hard link can't be made, like on another drive
+    HardLink,
+}
+
+// the caption columns the metadata gets
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum CaptionColumns {
+    // `text`, the caption as its caption file has it
+    Text,
+    // `tags`, the tags as a list
+    Tags,
+    Both,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct HuggingFaceExportOptions {
+    pub columns: CaptionColumns,
+    pub transfer: ImageTransfer,
+}
+
+impl Default for HuggingFaceExportOptions {
+    fn default() -> Self {
+        HuggingFaceExportOptions { columns: CaptionColumns::Both, transfer: ImageTransfer::Copy }
+    }
+}
+
+fn write_error(path: &Path) -> DatasetError {
+    DatasetError::new(DatasetErrorType::Write, Some(path.to_string_lossy().to_string()))
+}
+
+fn read_error(path: &Path) -> DatasetError {
+    DatasetError::new(DatasetErrorType::Read, Some(path.to_string_lossy().to_string()))
+}
+
+// An image file replaced in place could be a hard link to the original from an earlier export,
+// so the old file is removed first instead of being written through.
+fn transfer_image(source: &Path, destination: &Path, transfer: ImageTransfer) -> io::Result<()> {
+    if destination.exists() {
+        remove_file(destination)?;
+    }
+    if transfer == ImageTransfer::HardLink && hard_link(source, destination).is_ok() {
+        return Ok(());
+    }
+    copy(source, destination).map(|_| ())
+}
+
+impl Dataset {
+    // Writes the dataset to `output` in the imagefolder layout. The captions are the tags in memory with the pinned tags
+    // in front, like saving would write them, so unsaved edits are exported too. Returns how many images were exported.
+    // `output` can't be inside the dataset, the exported images would be read as part of the dataset the next time it is opened.
+    pub fn export_huggingface(&self, output: &Path, options: &HuggingFaceExportOptions) -> Result<usize, DatasetError> {
+        if resolve(output).starts_with(resolve(Path::new(&self.path))) {
+            return Err(DatasetError::new(DatasetErrorType::Export, Some(output.to_string_lossy().to_string())));
+        }
+
+        let mut metadata = String::new();
+        for image in self.data.iter() {
+            let destination = output.join(&image.relative_path);
+            if let Some(parent) = destination.parent() {
+                create_dir_all(parent).map_err(|_| write_error(parent))?;
+            }
+            transfer_image(Path::new(&image.path), &destination, options.transfer).map_err(|_| write_error(&destination))?;
+
+            let tags: Vec<String> = pin_tags(&self.settings.pinned_tags, &image.tags).into_iter().filter(|tag| !tag.is_empty()).collect();
+            let mut row = Map::new();
+            row.insert("file_name".to_string(), Value::from(image.relative_path.clone()));
+            if options.columns != CaptionColumns::Tags {
+                row.insert("text".to_string(), Value::from(self.caption_format.join(&tags)));
+            }
+            if options.columns != CaptionColumns::Text {
+                row.insert("tags".to_string(), Value::from(tags));
+            }
+            metadata.push_str(&Value::Object(row).to_string());
+            metadata.push('\n');
+        }
+
+        let metadata_path = output.join(METADATA_FILE);
+        write_atomic(&metadata_path, metadata.as_bytes()).map_err(|_| write_error(&metadata_path))?;
+
+        Ok(self.data.len())
+    }
+}
+
+// Turns an imagefolder, a folder with a `metadata.jsonl`, into a dataset at `destination`: every image in the
+// metadata gets a caption file next to it, and caption files already there are overwritten.
+// With `destination` being `source` only the caption files are written. Returns how many images were imported.
+pub fn import_huggingface(source: &Path, destination: &Path, transfer: ImageTransfer) -> Result<usize, DatasetError> {
+    let metadata_path = source.join(METADATA_FILE);
+    let metadata = read_to_string(&metadata_path).map_err(|_| read_error(&metadata_path))?;
+    let in_place = resolve(source) == resolve(destination);
+
+    let mut imported = 0;
+    for (index, line) in metadata.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        // a row we can't make sense of stops the import, pointing at its line in the metadata
+        let line_error = || DatasetError::new(DatasetErrorType::Read, Some(format!("{}:{}", metadata_path.to_string_lossy(), index + 1)));
+        let row = match serde_json::from_str::<Value>(line) {
+            Ok(Value::Object(row)) => row,
+            _ => return Err(line_error())
+        };
+        // `file_name` has to stay inside the folder, so a row can't make us write anywhere else
+        let file_name = match row.get("file_name").and_then(|file_name| file_name.as_str()) {
+            Some(file_name) if !file_name.is_empty() && Path::new(file_name).components().all(|component| matches!(component, Component::Normal(_))) => file_name,
+            _ => return Err(line_error())
+        };
+
+        let source_image = source.join(file_name);
+        if !source_image.is_file() {
+            return Err(read_error(&source_image));
+        }
+
+        let image_path = destination.join(file_name);
+        if !in_place {
+            if let Some(parent) = image_path.parent() {
+                create_dir_all(parent).map_err(|_| write_error(parent))?;
+            }
+            transfer_image(&source_image, &image_path, transfer).map_err(|_| write_error(&image_path))?;
+        }
+
+        // a list of tags is written the way the app writes captions by default
+        let caption = object_caption(&row).map(|caption| caption.to_text(CaptionFormat::default())).unwrap_or_default();
+        let caption_path = image_path.with_extension("txt");
+        write_atomic(&caption_path, caption.as_bytes()).map_err(|_| write_error(&caption_path))?;
+        imported += 1;
+    }
+
+    Ok(imported)
+}
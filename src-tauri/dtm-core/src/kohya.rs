@@ -0,0 +1,145 @@
+// Kohya's sd-scripts train on folders of images, every folder is a subset that is repeated `N` times per epoch when
+// it is named `N_name`. `dataset_config.toml` says the same thing explicitly, and is what the trainer reads:
+//
+//   [general]
+//   caption_extension = ".txt"
+//   caption_separator = ","
+//   keep_tokens = 1
+//
+//   [[datasets]]
+//   resolution = 512
+//
+//     [[datasets.subsets]]
+//     image_dir = "/data/dataset/10_subject"
+//     num_repeats = 10
+
+use std::path::Path;
+
+use serde::{ Serialize, Deserialize };
+use serde_json::Value;
+
+use super::caption::CaptionFormat;
+use super::dataset::{Dataset, DatasetError, DatasetErrorType};
+use super::file::write_atomic;
+use super::source::CaptionSource;
+
+pub const CONFIG_FILE: &str = "dataset_config.toml";
+pub const DEFAULT_RESOLUTION: u32 = 512;
+
+// A folder of the dataset with images in it, as a subset of the training data.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct KohyaSubset {
+    // relative to the dataset root and '/' separated, empty for the root itself
+    pub folder: String,
+    pub images: usize,
+    // the repeats set in the app, or else the ones in the folder name, or else 1
+    pub repeats: u32,
+    // the repeats in the folder name, what `repeats` goes back to when it is reset
+    pub folder_repeats: Option<u32>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct KohyaConfigOptions {
+    pub resolution: u32,
+    // how many tags at the start of a caption stay in place when the trainer shuffles them, the number of pinned tags if not given.
+    // only for captions the trainer can split into tags, see `kohya_config`
+    pub keep_tokens: Option<usize>,
+}
+
+impl Default for KohyaConfigOptions {
+    fn default() -> Self {
+        KohyaConfigOptions { resolution: DEFAULT_RESOLUTION, keep_tokens: None }
+    }
+}
+
+// the repeats in a Kohya folder name, `10_subject` repeats 10 times
+pub fn folder_repeats(folder_name: &str) -> Option<u32> {
+    let (repeats, _) = folder_name.split_once('_')?;
+    if repeats.is_empty() || !repeats.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    repeats.parse().ok()
+}
+
+// a TOML basic string escapes the same way a JSON string does
+fn toml_string(value: &str) -> String {
+    Value::from(value).to_string()
+}
+
+impl Dataset {
+    // Every folder with images in it, in the order of the images, with the repeats it is trained with.
+    pub fn kohya_subsets(&self) -> Vec<KohyaSubset> {
+        let mut subsets: Vec<KohyaSubset> = Vec::new();
+
+        for image in self.data.iter() {
+            let folder = match image.relative_path.rfind('/') {
+                Some(index) => &image.relative_path[..index],
+                None => ""
+            };
+
+            match subsets.iter_mut().find(|subset| subset.folder == folder) {
+                Some(subset) => subset.images += 1,
+                None => {
+                    let folder_repeats = folder_repeats(folder.rsplit('/').next().unwrap_or(folder));
+                    let repeats = self.settings.repeats.get(folder).copied().or(folder_repeats).unwrap_or(1);
+                    subsets.push(KohyaSubset { folder: folder.to_string(), images: 1, repeats, folder_repeats });
+                }
+            }
+        }
+
+        subsets
+    }
+
+    // The `dataset_config.toml` for training on the dataset as it is on disk, so it should be saved first.
+    // The trainer reads the captions from the files next to the images, a dataset with its captions anywhere else can't be trained on as is.
+    pub fn kohya_config(&self, options: &KohyaConfigOptions) -> Result<String, DatasetError> {
+        let caption_extension = match self.caption_source {
+            CaptionSource::Txt => ".txt",
+            CaptionSource::Caption => ".caption",
+            _ => {
+                let reason = "the trainer reads captions from .txt or .caption files next to the images, these captions are somewhere else";
+                return Err(DatasetError::new(DatasetErrorType::Kohya(reason.to_string()), Some(self.path.clone())));
+            }
+        };
+        // the trainer splits a caption into tags on `caption_separator` to keep and shuffle them. a sentence isn't
+        // made of tags, so it is trained on as a whole and keeps nothing in place
+        let caption_separator = match self.caption_format {
+            CaptionFormat::Comma => Some(","),
+            CaptionFormat::Period => Some("."),
+            CaptionFormat::Sentence => None,
+            // the trainer only reads the first line of a caption file, it would never see anything past the first tag
+            CaptionFormat::Newline => {
+                let reason = "the trainer only reads the first line of a caption file, so it can't use captions with one tag per line";
+                return Err(DatasetError::new(DatasetErrorType::Kohya(reason.to_string()), Some(self.path.clone())));
+            }
+        };
+        // the pinned tags are what every caption starts with, so they are what stays in front
+        let keep_tokens = options.keep_tokens.unwrap_or(self.settings.pinned_tags.len());
+
+        let mut config = String::new();
+        config.push_str("[general]\n");
+        config.push_str(&format!("caption_extension = {}\n", toml_string(caption_extension)));
+        if let Some(caption_separator) = caption_separator {
+            config.push_str(&format!("caption_separator = {}\n", toml_string(caption_separator)));
+            config.push_str(&format!("keep_tokens = {}\n", keep_tokens));
+        }
+        config.push_str("\n[[datasets]]\n");
+        config.push_str(&format!("resolution = {}\n", options.resolution));
+
+        let root = Path::new(&self.path);
+        for subset in self.kohya_subsets() {
+            let image_dir = subset.folder.split('/').filter(|part| !part.is_empty()).fold(root.to_path_buf(), |path, part| path.join(part));
+            config.push_str("\n  [[datasets.subsets]]\n");
+            config.push_str(&format!("  image_dir = {}\n", toml_string(&image_dir.to_string_lossy())));
+            config.push_str(&format!("  num_repeats = {}\n", subset.repeats));
+        }
+
+        Ok(config)
+    }
+
+    pub fn write_kohya_config(&self, output: &Path, options: &KohyaConfigOptions) -> Result<(), DatasetError> {
+        let config = self.kohya_config(options)?;
+        write_atomic(output, config.as_bytes()).map_err(|_| DatasetError::new(DatasetErrorType::Write, Some(output.to_string_lossy().to_string())))
+    }
+}
@@ -0,0 +1,27 @@
+// Everything the app knows about datasets and their captions, without any of the app itself,
+// so the `dtm` command line tool reads and writes captions exactly the way the app does.
+
+pub mod backup;
+pub mod bulk;
+pub mod caption;
+pub mod category;
+pub mod conflict;
+pub mod csv;
+pub mod dataset;
+pub mod file;
+pub mod history;
+pub mod huggingface;
+pub mod kohya;
+pub mod logger;
+pub mod normalize;
+pub mod pinned;
+pub mod query;
+pub mod rules;
+pub mod settings;
+pub mod sort;
+pub mod source;
+pub mod stats;
+pub mod vocabulary;
+pub mod watcher;
+pub mod webdataset;
+pub mod weight;
\ No newline at end of file
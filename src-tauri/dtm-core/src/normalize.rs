@@ -0,0 +1,214 @@
+use serde::{ Serialize, Deserialize };
+use unicode_normalization::UnicodeNormalization;
+
+use super::dataset::Dataset;
+use super::history::ImageTagChange;
+use super::weight::{unescape, WeightedTag};
+
+// tags that are faces made of underscores, turning their underscores into spaces would break them
+const KAOMOJI: [&str; 19] = [
+    "0_0", "(o)_(o)", "+_+", "+_-", "._.", "<o>_<o>", "<|>_<|>", "=_=", ">_<", "3_3",
+    "6_9", ">_o", "@_@", "^_^", "o_o", "u_u", "x_x", "|_|", "||_||",
+];
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum WordSeparator {
+    Keep,
+    // red_hair -> red hair
+    Spaces,
+    // red hair -> red_hair
+    Underscores,
+}
+
+// parentheses mean prompt weights to most trainers, so tags like `hair_(style)` usually need them stripped or escaped.
+// only the text of a tag is touched, emphasis around it like `(hair_(style):1.2)` stays as it is
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Parentheses {
+    Keep,
+    // hair (style) -> hair style
+    Strip,
+    // hair (style) -> hair \(style\), parentheses that are already escaped are left alone
+    Escape,
+}
+
+// Which normalization steps run over the tags. They run in the order of the fields, except that underscores
+// always become spaces before whitespace is collapsed, and only turn into underscores again at the very end.
+// Everything is off by default, so loading a dataset leaves its tags as they are until the user opts in.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(default)]
+pub struct NormalizeOptions {
+    pub unicode_nfc: bool,
+    pub lowercase: bool,
+    pub word_separator: WordSeparator,
+    pub parentheses: Parentheses,
+    // also trims the tag
+    pub collapse_whitespace: bool,
+    // keeps the first of tags that end up the same
+    pub dedupe: bool,
+}
+
+impl Default for NormalizeOptions {
+    fn default() -> Self {
+        NormalizeOptions {
+            unicode_nfc: false,
+            lowercase: false,
+            word_separator: WordSeparator::Keep,
+            parentheses: Parentheses::Keep,
+            collapse_whitespace: false,
+            dedupe: false,
+        }
+    }
+}
+
+impl NormalizeOptions {
+    pub fn normalize_tag(&self, tag: &str) -> String {
+        let mut tag = if self.unicode_nfc { tag.nfc().collect::<String>() } else { tag.to_string() };
+
+        if self.lowercase {
+            tag = tag.to_lowercase();
+        }
+
+        // both separators start out as spaces, so `red__hair` and `red  hair` collapse the same way
+        let words = self.word_separator != WordSeparator::Keep && !KAOMOJI.contains(&tag.as_str());
+        if words {
+            tag = tag.replace('_', " ");
+        }
+
+        match self.parentheses {
+            Parentheses::Keep => {},
+            Parentheses::Strip => tag = map_tag_text(&tag, strip_parentheses),
+            Parentheses::Escape => tag = map_tag_text(&tag, escape_parentheses),
+        }
+
+        if self.collapse_whitespace {
+            tag = tag.split_whitespace().collect::<Vec<&str>>().join(" ");
+        }
+
+        if words && self.word_separator == WordSeparator::Underscores {
+            tag = map_tag_text(&tag, |text| text.trim().replace(' ', "_"));
+        }
+
+        tag
+    }
+
+    // The normalized tags, or None if normalizing doesn't change them.
+    // A tag that normalizes down to nothing is dropped.
+    pub fn normalize(&self, tags: &[String]) -> Option<Vec<String>> {
+        let mut new_tags: Vec<String> = Vec::with_capacity(tags.len());
+        for tag in tags {
+            let normalized = self.normalize_tag(tag);
+            if normalized.is_empty() && !tag.is_empty() {
+                continue;
+            }
+            if self.dedupe && new_tags.contains(&normalized) {
+                continue;
+            }
+            new_tags.push(normalized);
+        }
+
+        if new_tags == tags {
+            None
+        } else {
+            Some(new_tags)
+        }
+    }
+}
+
+// runs `f` over the escaped text of a tag and puts the tag's emphasis back around the result
+fn map_tag_text(tag: &str, f: fn(&str) -> String) -> String {
+    let mut weighted = WeightedTag::parse(tag);
+    weighted.escaped = f(&weighted.escaped);
+    weighted.text = unescape(&weighted.escaped);
+    weighted.to_string()
+}
+
+fn strip_parentheses(tag: &str) -> String {
+    let mut stripped = String::with_capacity(tag.len());
+    let mut chars = tag.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            // an escaped parenthesis goes together with its backslash
+            '\\' if matches!(chars.peek(), Some('(') | Some(')')) => {
+                chars.next();
+            },
+            '(' | ')' => {},
+            _ => stripped.push(c)
+        }
+    }
+    // a tag like "hair ()" leaves a space behind at the end
+    stripped.trim().to_string()
+}
+
+fn escape_parentheses(tag: &str) -> String {
+    let mut escaped = String::with_capacity(tag.len());
+    let mut previous = None;
+    for c in tag.chars() {
+        if (c == '(' || c == ')') && previous != Some('\\') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+        previous = Some(c);
+    }
+    escaped
+}
+
+impl Dataset {
+    // Normalizes the tags of every image, returning the new dataset and what changed per image.
+    // Like `apply_rules`, only the tags in memory change and `dry_run` leaves the dataset as it is.
+    pub fn normalize_tags(&self, options: &NormalizeOptions, dry_run: bool) -> (Dataset, Vec<ImageTagChange>) {
+        let mut dataset = self.clone();
+        let mut changes = Vec::new();
+
+        for image in dataset.data.iter_mut() {
+            if let Some(tags) = options.normalize(&image.tags) {
+                changes.push(ImageTagChange { relative_path: image.relative_path.clone(), before: image.tags.clone(), after: tags.clone() });
+                if !dry_run {
+                    image.tags = tags;
+                }
+            }
+        }
+
+        (dataset, changes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn options(parentheses: Parentheses) -> NormalizeOptions {
+        NormalizeOptions { parentheses, ..NormalizeOptions::default() }
+    }
+
+    #[test]
+    fn parentheses_leave_weights_alone() {
+        let strip = options(Parentheses::Strip);
+        assert_eq!(strip.normalize_tag("name_(artist)"), "name_artist");
+        assert_eq!(strip.normalize_tag("(masterpiece:1.2)"), "(masterpiece:1.2)");
+        assert_eq!(strip.normalize_tag("((masterpiece))"), "((masterpiece))");
+        assert_eq!(strip.normalize_tag("(name_(artist):1.2)"), "(name_artist:1.2)");
+
+        let escape = options(Parentheses::Escape);
+        assert_eq!(escape.normalize_tag("name_(artist)"), "name_\\(artist\\)");
+        assert_eq!(escape.normalize_tag("(masterpiece:1.2)"), "(masterpiece:1.2)");
+        assert_eq!(escape.normalize_tag("[name_(artist)]"), "[name_\\(artist\\)]");
+        assert_eq!(escape.normalize_tag("name_\\(artist\\)"), "name_\\(artist\\)");
+    }
+
+    #[test]
+    fn word_separators_collapse_like_whitespace() {
+        for word_separator in [WordSeparator::Spaces, WordSeparator::Underscores] {
+            let collapse = NormalizeOptions { word_separator, collapse_whitespace: true, ..NormalizeOptions::default() };
+            let expected = if word_separator == WordSeparator::Spaces { "red hair" } else { "red_hair" };
+            for tag in ["red__hair", "red  hair", " red _ hair ", "_red_hair_"] {
+                assert_eq!(collapse.normalize_tag(tag), expected, "{:?} {}", word_separator, tag);
+            }
+        }
+
+        let underscores = NormalizeOptions { word_separator: WordSeparator::Underscores, ..NormalizeOptions::default() };
+        assert_eq!(underscores.normalize_tag("(red hair : 1.2)"), "(red_hair : 1.2)");
+        assert_eq!(underscores.normalize_tag("^_^"), "^_^");
+    }
+}
@@ -0,0 +1,63 @@
+// Pinned tags are the tags every caption has to start with, in order, like a trigger word followed by a class token.
+// Trainers that shuffle captions keep the first `keep_tokens` tags in place, so these are the tags that matter there.
+
+use serde::{ Serialize, Deserialize };
+
+use super::dataset::Dataset;
+
+// An image whose tags don't start with the pinned tags.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct PinnedTagViolation {
+    pub relative_path: String,
+    // pinned tags the image doesn't have at all
+    pub missing: Vec<String>,
+    // the tags the image has where the pinned tags should be
+    pub leading: Vec<String>,
+}
+
+// The tags with the pinned tags moved (or added) to the front, everything else keeps its order.
+pub fn pin_tags(pinned: &[String], tags: &[String]) -> Vec<String> {
+    let pinned: Vec<&String> = pinned.iter().filter(|tag| !tag.is_empty()).collect();
+    if pinned.is_empty() {
+        return tags.to_vec();
+    }
+
+    let mut new_tags: Vec<String> = pinned.iter().map(|tag| tag.to_string()).collect();
+    new_tags.extend(tags.iter().filter(|tag| !tag.is_empty() && !pinned.contains(tag)).cloned());
+    new_tags
+}
+
+impl Dataset {
+    pub fn pinned_tag_violations(&self) -> Vec<PinnedTagViolation> {
+        let pinned = &self.settings.pinned_tags;
+        self.data.iter().filter_map(|image| {
+            if pin_tags(pinned, &image.tags) == image.tags {
+                return None;
+            }
+
+            Some(PinnedTagViolation {
+                relative_path: image.relative_path.clone(),
+                missing: pinned.iter().filter(|tag| !tag.is_empty() && !image.tags.contains(tag)).cloned().collect(),
+                leading: image.tags.iter().take(pinned.len()).cloned().collect(),
+            })
+        }).collect()
+    }
+
+    // Puts the pinned tags in front on the images at `indices`.
+    // Returns the new dataset and the indices of the images that changed.
+    pub fn enforce_pinned_tags(&self, indices: &[usize]) -> (Dataset, Vec<usize>) {
+        let mut dataset = self.clone();
+        let mut changed = Vec::new();
+
+        for index in indices {
+            let image = &mut dataset.data[*index];
+            let tags = pin_tags(&self.settings.pinned_tags, &image.tags);
+            if tags != image.tags {
+                image.tags = tags;
+                changed.push(*index);
+            }
+        }
+
+        (dataset, changed)
+    }
+}
@@ -0,0 +1,324 @@
+// A small query language for finding images in a dataset.
+//
+//   cat AND (sitting OR lying) AND NOT blurry
+//   "red hair" blue*          terms next to each other are AND-ed, quotes keep spaces in a tag
+//   tags<5  tags>=10  tags=0  compares the number of tags on an image
+//   file:*.png  path:10_subject/*   globs over the image name, or its path relative to the dataset root
+//   file:"my file.png"        quotes keep spaces in the value of a predicate too
+//   category:character        images with at least one tag in the category
+//
+// Tag terms match whole tags, case-insensitively. `*` matches any run of characters and `?` a single one,
+// so `blue*` is a prefix match. AND, OR and NOT have to be written in capitals, NOT binds tightest and OR loosest.
+
+use std::error::Error;
+
+use super::category::{TagCategories, TagCategory};
+use super::dataset::{Dataset, DatasetImage};
+
+#[derive(Debug)]
+pub struct QueryError {
+    pub message: String,
+    // character offset in the query where things went wrong
+    pub position: usize,
+}
+
+impl QueryError {
+    fn new(message: &str, position: usize) -> QueryError {
+        QueryError { message: message.to_string(), position }
+    }
+}
+
+impl Error for QueryError {}
+
+impl std::fmt::Display for QueryError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{} (at position {})", self.message, self.position)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Comparison {
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+    Equal,
+    NotEqual,
+}
+
+impl Comparison {
+    fn matches(&self, left: usize, right: usize) -> bool {
+        match self {
+            Comparison::Less => left < right,
+            Comparison::LessOrEqual => left <= right,
+            Comparison::Greater => left > right,
+            Comparison::GreaterOrEqual => left >= right,
+            Comparison::Equal => left == right,
+            Comparison::NotEqual => left != right,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Query {
+    // matches an image that has a tag matching the (lowercased) pattern
+    Tag(String),
+    TagCount(Comparison, usize),
+    FileName(String),
+    FilePath(String),
+    Category(TagCategory),
+    Not(Box<Query>),
+    And(Box<Query>, Box<Query>),
+    Or(Box<Query>, Box<Query>),
+}
+
+// the predicates that take a value, which can be quoted
+const PREDICATES: [&str; 3] = ["file:", "path:", "category:"];
+
+#[derive(Debug, PartialEq)]
+enum Token {
+    Word(String),
+    // a quoted word, never taken for a keyword or predicate
+    Quoted(String),
+    And,
+    Or,
+    Not,
+    Open,
+    Close,
+}
+
+fn tokenize(query: &str) -> Result<Vec<(Token, usize)>, QueryError> {
+    let mut tokens = Vec::new();
+    let chars: Vec<char> = query.chars().collect();
+    let mut index = 0;
+
+    while index < chars.len() {
+        let c = chars[index];
+        if c.is_whitespace() {
+            index += 1;
+        } else if c == '(' {
+            tokens.push((Token::Open, index));
+            index += 1;
+        } else if c == ')' {
+            tokens.push((Token::Close, index));
+            index += 1;
+        } else if c == '"' {
+            let (word, end) = quoted(&chars, index)?;
+            tokens.push((Token::Quoted(word), index));
+            index = end;
+        } else {
+            let start = index;
+            let mut word = String::new();
+            while index < chars.len() && !chars[index].is_whitespace() && chars[index] != '(' && chars[index] != ')' && chars[index] != '"' {
+                word.push(chars[index]);
+                index += 1;
+            }
+            // `file:"my file.png"` is one word, the quotes only keep the value together
+            if index < chars.len() && chars[index] == '"' && PREDICATES.contains(&word.as_str()) {
+                let (value, end) = quoted(&chars, index)?;
+                word.push_str(&value);
+                index = end;
+            }
+            let token = match word.as_str() {
+                "AND" => Token::And,
+                "OR" => Token::Or,
+                "NOT" => Token::Not,
+                _ => Token::Word(word)
+            };
+            tokens.push((token, start));
+        }
+    }
+
+    Ok(tokens)
+}
+
+// the text between the quote at `start` and the next one, and the index after the closing quote
+fn quoted(chars: &[char], start: usize) -> Result<(String, usize), QueryError> {
+    let mut index = start + 1;
+    let mut word = String::new();
+    while index < chars.len() && chars[index] != '"' {
+        word.push(chars[index]);
+        index += 1;
+    }
+    if index == chars.len() {
+        return Err(QueryError::new("Missing closing quote", start));
+    }
+    Ok((word, index + 1))
+}
+
+struct Parser {
+    tokens: Vec<(Token, usize)>,
+    index: usize,
+    end: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.index).map(|(token, _)| token)
+    }
+
+    fn position(&self) -> usize {
+        self.tokens.get(self.index).map(|(_, position)| *position).unwrap_or(self.end)
+    }
+
+    fn parse_or(&mut self) -> Result<Query, QueryError> {
+        let mut query = self.parse_and()?;
+        while self.peek() == Some(&Token::Or) {
+            self.index += 1;
+            let right = self.parse_and()?;
+            query = Query::Or(Box::new(query), Box::new(right));
+        }
+        Ok(query)
+    }
+
+    fn parse_and(&mut self) -> Result<Query, QueryError> {
+        let mut query = self.parse_not()?;
+        loop {
+            match self.peek() {
+                Some(Token::And) => {
+                    self.index += 1;
+                },
+                // two terms next to each other are AND-ed as well
+                Some(Token::Word(_)) | Some(Token::Quoted(_)) | Some(Token::Not) | Some(Token::Open) => {},
+                _ => break
+            }
+            let right = self.parse_not()?;
+            query = Query::And(Box::new(query), Box::new(right));
+        }
+        Ok(query)
+    }
+
+    fn parse_not(&mut self) -> Result<Query, QueryError> {
+        if self.peek() == Some(&Token::Not) {
+            self.index += 1;
+            let query = self.parse_not()?;
+            return Ok(Query::Not(Box::new(query)));
+        }
+        self.parse_primary()
+    }
+
+    fn parse_primary(&mut self) -> Result<Query, QueryError> {
+        let position = self.position();
+        let token = match self.tokens.get(self.index) {
+            Some((token, _)) => token,
+            None => return Err(QueryError::new("Expected a tag or a '('", position))
+        };
+
+        let query = match token {
+            Token::Open => {
+                self.index += 1;
+                let query = self.parse_or()?;
+                if self.peek() != Some(&Token::Close) {
+                    return Err(QueryError::new("Missing ')'", self.position()));
+                }
+                query
+            },
+            Token::Quoted(word) => Query::Tag(word.to_lowercase()),
+            Token::Word(word) => parse_word(word, position)?,
+            Token::Close => return Err(QueryError::new("Unexpected ')'", position)),
+            Token::And | Token::Or | Token::Not => return Err(QueryError::new("Expected a tag or a '(' before this keyword", position)),
+        };
+
+        self.index += 1;
+        Ok(query)
+    }
+}
+
+// a bare word is a tag, unless it is one of the predicates
+fn parse_word(word: &str, position: usize) -> Result<Query, QueryError> {
+    if let Some(pattern) = word.strip_prefix("file:") {
+        return Ok(Query::FileName(pattern.to_lowercase()));
+    }
+    if let Some(pattern) = word.strip_prefix("path:") {
+        return Ok(Query::FilePath(pattern.to_lowercase()));
+    }
+    if let Some(category) = word.strip_prefix("category:") {
+        return match TagCategory::parse(category) {
+            Some(category) => Ok(Query::Category(category)),
+            None => Err(QueryError::new("Unknown category", position + "category:".len()))
+        };
+    }
+    if let Some(rest) = word.strip_prefix("tags") {
+        let comparisons = [
+            ("<=", Comparison::LessOrEqual),
+            (">=", Comparison::GreaterOrEqual),
+            ("!=", Comparison::NotEqual),
+            ("<", Comparison::Less),
+            (">", Comparison::Greater),
+            ("=", Comparison::Equal),
+        ];
+        for (operator, comparison) in comparisons {
+            if let Some(count) = rest.strip_prefix(operator) {
+                return match count.parse::<usize>() {
+                    Ok(count) => Ok(Query::TagCount(comparison, count)),
+                    Err(_) => Err(QueryError::new("Expected a number of tags", position + "tags".len() + operator.len()))
+                };
+            }
+        }
+    }
+
+    Ok(Query::Tag(word.to_lowercase()))
+}
+
+impl Query {
+    pub fn parse(query: &str) -> Result<Query, QueryError> {
+        let tokens = tokenize(query)?;
+        let mut parser = Parser { tokens, index: 0, end: query.chars().count() };
+
+        let parsed = parser.parse_or()?;
+        if parser.index < parser.tokens.len() {
+            return Err(QueryError::new("Unexpected input", parser.position()));
+        }
+
+        Ok(parsed)
+    }
+
+    pub fn matches(&self, image: &DatasetImage, categories: &TagCategories) -> bool {
+        match self {
+            Query::Tag(pattern) => image.tags.iter().any(|tag| glob_match(pattern, &tag.to_lowercase())),
+            Query::TagCount(comparison, count) => comparison.matches(image.tags.iter().filter(|tag| !tag.is_empty()).count(), *count),
+            Query::FileName(pattern) => glob_match(pattern, &image.name.to_lowercase()),
+            Query::FilePath(pattern) => glob_match(pattern, &image.relative_path.to_lowercase()),
+            Query::Category(category) => image.tags.iter().any(|tag| !tag.is_empty() && categories.get(tag) == *category),
+            Query::Not(query) => !query.matches(image, categories),
+            Query::And(left, right) => left.matches(image, categories) && right.matches(image, categories),
+            Query::Or(left, right) => left.matches(image, categories) || right.matches(image, categories),
+        }
+    }
+}
+
+impl Dataset {
+    // the relative paths of the images matching the query, in dataset order
+    pub fn query_images(&self, query: &str) -> Result<Vec<String>, QueryError> {
+        let query = Query::parse(query)?;
+        Ok(self.data.iter().filter(|image| query.matches(image, &self.categories)).map(|image| image.relative_path.clone()).collect())
+    }
+}
+
+// `*` matches any run of characters, `?` any single character
+pub fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+
+    let (mut p, mut t) = (0, 0);
+    // where the last `*` was, and how much of the text it has eaten so far
+    let mut star: Option<(usize, usize)> = None;
+
+    while t < text.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == text[t]) {
+            p += 1;
+            t += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            star = Some((p, t));
+            p += 1;
+        } else if let Some((star_p, star_t)) = star {
+            p = star_p + 1;
+            t = star_t + 1;
+            star = Some((star_p, star_t + 1));
+        } else {
+            return false;
+        }
+    }
+
+    pattern[p..].iter().all(|c| *c == '*')
+}
@@ -0,0 +1,167 @@
+use std::fs::{create_dir_all, read_to_string};
+use std::io;
+use std::path::{Path, PathBuf};
+
+use serde::{ Serialize, Deserialize };
+
+use super::dataset::Dataset;
+use super::file::write_atomic;
+use super::history::ImageTagChange;
+use super::settings::DATASET_APP_DIR;
+
+const RULES_FILE: &str = "rules.txt";
+
+// A rule from the dataset's rules file. The file has one rule per line:
+//
+//   # comments and empty lines are ignored
+//   kitty -> cat          alias, `kitty` is replaced by `cat`
+//   tabby_cat => cat      implication, an image with `tabby_cat` also gets `cat`
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum TagRule {
+    Alias { from: String, to: String },
+    Implication { from: String, implies: String },
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct TagRules {
+    pub rules: Vec<TagRule>,
+}
+
+impl TagRules {
+    pub fn path(dataset_path: &Path) -> PathBuf {
+        dataset_path.join(DATASET_APP_DIR).join(RULES_FILE)
+    }
+
+    // a dataset without a rules file has no rules. a rules file we can't parse is an error,
+    // silently skipping a rule the user wrote would be worse.
+    pub fn load(dataset_path: &Path) -> io::Result<TagRules> {
+        match read_to_string(TagRules::path(dataset_path)) {
+            Ok(contents) => TagRules::parse(&contents).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err)),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(TagRules::default()),
+            Err(err) => Err(err)
+        }
+    }
+
+    pub fn save(&self, dataset_path: &Path) -> io::Result<()> {
+        let path = TagRules::path(dataset_path);
+        if let Some(parent) = path.parent() {
+            create_dir_all(parent)?;
+        }
+
+        write_atomic(&path, self.to_file_contents().as_bytes())
+    }
+
+    pub fn parse(contents: &str) -> Result<TagRules, String> {
+        let mut rules = Vec::new();
+        for (index, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            // whichever arrow comes first splits the rule, so the tags themselves may contain the other one
+            let alias = line.find("->");
+            let implication = line.find("=>");
+            let (split, is_alias) = match (alias, implication) {
+                (Some(a), Some(i)) => if a < i { (a, true) } else { (i, false) },
+                (Some(a), None) => (a, true),
+                (None, Some(i)) => (i, false),
+                (None, None) => return Err(format!("line {}: expected `from -> to` or `from => implied`", index + 1))
+            };
+
+            let from = line[..split].trim().to_string();
+            let to = line[split + 2..].trim().to_string();
+            if from.is_empty() || to.is_empty() {
+                return Err(format!("line {}: both sides of a rule need a tag", index + 1));
+            }
+
+            rules.push(if is_alias {
+                TagRule::Alias { from, to }
+            } else {
+                TagRule::Implication { from, implies: to }
+            });
+        }
+
+        Ok(TagRules { rules })
+    }
+
+    pub fn to_file_contents(&self) -> String {
+        self.rules.iter().map(|rule| match rule {
+            TagRule::Alias { from, to } => format!("{} -> {}\n", from, to),
+            TagRule::Implication { from, implies } => format!("{} => {}\n", from, implies),
+        }).collect()
+    }
+
+    // follows aliases until it reaches a tag that isn't one. a cycle of aliases stops after going around once.
+    fn resolve_alias<'a>(&'a self, tag: &'a str) -> &'a str {
+        let mut tag = tag;
+        for _ in 0..self.rules.len() {
+            let next = self.rules.iter().find_map(|rule| match rule {
+                TagRule::Alias { from, to } if from == tag => Some(to.as_str()),
+                _ => None
+            });
+            match next {
+                Some(next) => tag = next,
+                None => break
+            }
+        }
+        tag
+    }
+
+    // The tags after applying the rules, or None if the rules don't change them.
+    // Aliases are replaced in place (dropping duplicates they create), then implied tags are appended,
+    // including the tags implied by implied tags.
+    pub fn apply(&self, tags: &[String]) -> Option<Vec<String>> {
+        let mut new_tags: Vec<String> = Vec::with_capacity(tags.len());
+        for tag in tags {
+            let tag = self.resolve_alias(tag);
+            if !new_tags.iter().any(|existing| existing == tag) {
+                new_tags.push(tag.to_string());
+            }
+        }
+
+        // new_tags grows while we walk it, so implications of implied tags are picked up as well
+        let mut index = 0;
+        while index < new_tags.len() {
+            for rule in self.rules.iter() {
+                if let TagRule::Implication { from, implies } = rule {
+                    if *from == new_tags[index] {
+                        let implied = self.resolve_alias(implies);
+                        if !new_tags.iter().any(|existing| existing == implied) {
+                            new_tags.push(implied.to_string());
+                        }
+                    }
+                }
+            }
+            index += 1;
+        }
+
+        if new_tags == tags {
+            None
+        } else {
+            Some(new_tags)
+        }
+    }
+}
+
+impl Dataset {
+    // Applies the rules to the tags of every image, returning the new dataset and what changed per image.
+    // Only the tags in memory change, the images show up as unsaved and are written on the next save.
+    // With `dry_run` the dataset is returned unchanged, so the report can be looked at first.
+    pub fn apply_rules(&self, rules: &TagRules, dry_run: bool) -> (Dataset, Vec<ImageTagChange>) {
+        let mut dataset = self.clone();
+        let mut changes = Vec::new();
+
+        for image in dataset.data.iter_mut() {
+            if let Some(tags) = rules.apply(&image.tags) {
+                changes.push(ImageTagChange { relative_path: image.relative_path.clone(), before: image.tags.clone(), after: tags.clone() });
+                if !dry_run {
+                    image.tags = tags;
+                }
+            }
+        }
+
+        (dataset, changes)
+    }
+}
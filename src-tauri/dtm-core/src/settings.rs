@@ -0,0 +1,63 @@
+use std::collections::BTreeMap;
+use std::fs::{create_dir_all, read_to_string};
+use std::path::{Path, PathBuf};
+
+use serde::{ Serialize, Deserialize };
+
+use super::backup::BackupRetention;
+use super::dataset::DEFAULT_MAX_DEPTH;
+use super::file::write_atomic;
+use super::normalize::NormalizeOptions;
+use super::source::CaptionSource;
+
+// everything the app keeps inside a dataset lives in this folder. it is hidden, so it is never read as part of the dataset.
+pub const DATASET_APP_DIR: &str = ".dtm";
+const SETTINGS_FILE: &str = "settings.json";
+
+// Per dataset settings, stored in `.dtm/settings.json` inside the dataset so they travel with it.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct DatasetSettings {
+    pub backup_retention: BackupRetention,
+    // applied to the tags when the dataset is opened
+    pub normalization: NormalizeOptions,
+    // tags every caption starts with, in this order. see `pinned`
+    pub pinned_tags: Vec<String>,
+    // the tag vocabulary to autocomplete from, loaded with the dataset
+    pub vocabulary_path: Option<String>,
+    // where the captions are, detected when the dataset is opened if it isn't set
+    pub caption_source: Option<CaptionSource>,
+    // the Kohya repeats of a folder by its path relative to the dataset root, see `kohya`.
+    // a folder that isn't in here repeats as often as its name says
+    pub repeats: BTreeMap<String, u32>,
+    // how many levels of subfolders images are read from, `DEFAULT_MAX_DEPTH` if it isn't set
+    pub max_depth: Option<usize>,
+}
+
+impl DatasetSettings {
+    pub fn max_depth(&self) -> usize {
+        self.max_depth.unwrap_or(DEFAULT_MAX_DEPTH)
+    }
+
+    pub fn path(dataset_path: &Path) -> PathBuf {
+        dataset_path.join(DATASET_APP_DIR).join(SETTINGS_FILE)
+    }
+
+    // a dataset without a settings file (or with one we can't make sense of) gets the defaults
+    pub fn load(dataset_path: &Path) -> DatasetSettings {
+        match read_to_string(DatasetSettings::path(dataset_path)) {
+            Ok(contents) => serde_json::from_str(&contents).unwrap_or_default(),
+            Err(_) => DatasetSettings::default()
+        }
+    }
+
+    pub fn save(&self, dataset_path: &Path) -> std::io::Result<()> {
+        let path = DatasetSettings::path(dataset_path);
+        if let Some(parent) = path.parent() {
+            create_dir_all(parent)?;
+        }
+
+        let contents = serde_json::to_string_pretty(self).map_err(|err| std::io::Error::new(std::io::ErrorKind::Other, err))?;
+        write_atomic(&path, contents.as_bytes())
+    }
+}
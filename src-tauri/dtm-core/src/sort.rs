@@ -0,0 +1,102 @@
+use std::collections::{HashMap, HashSet};
+
+use serde::{ Serialize, Deserialize };
+
+use super::dataset::{Dataset, DatasetImage};
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum SortStrategy {
+    // case-insensitive
+    Alphabetical,
+    // tags on the most images in the dataset first
+    Frequency,
+    // in the order of `TagCategory`, character tags first
+    Category,
+    // the listed tags first, in the listed order
+    Priority { tags: Vec<String> },
+}
+
+// Everything a strategy needs to know about the dataset, worked out once for all the images being sorted.
+pub struct TagSorter<'a> {
+    strategy: &'a SortStrategy,
+    dataset: &'a Dataset,
+    frequencies: HashMap<&'a str, usize>,
+}
+
+impl<'a> TagSorter<'a> {
+    pub fn new(dataset: &'a Dataset, strategy: &'a SortStrategy) -> TagSorter<'a> {
+        let mut frequencies = HashMap::new();
+        if let SortStrategy::Frequency = strategy {
+            for image in dataset.data.iter() {
+                let tags: HashSet<&str> = image.tags.iter().map(|tag| tag.as_str()).collect();
+                for tag in tags {
+                    *frequencies.entry(tag).or_insert(0) += 1;
+                }
+            }
+        }
+
+        TagSorter { strategy, dataset, frequencies }
+    }
+
+    // Pinned tags the image has stay in front, in their pinned order. The rest is sorted with the strategy,
+    // tags the strategy can't tell apart keep the order they had.
+    pub fn sort(&self, tags: &[String]) -> Vec<String> {
+        let pinned = &self.dataset.settings.pinned_tags;
+        let mut sorted: Vec<String> = pinned.iter().filter(|tag| tags.contains(tag)).cloned().collect();
+
+        let mut rest: Vec<&String> = tags.iter().filter(|tag| !pinned.contains(tag)).collect();
+        match self.strategy {
+            SortStrategy::Alphabetical => rest.sort_by_key(|tag| tag.to_lowercase()),
+            SortStrategy::Frequency => rest.sort_by(|a, b| {
+                let a = self.frequencies.get(a.as_str()).unwrap_or(&0);
+                let b = self.frequencies.get(b.as_str()).unwrap_or(&0);
+                b.cmp(a)
+            }),
+            SortStrategy::Category => rest.sort_by_key(|tag| self.dataset.categories.get(tag)),
+            SortStrategy::Priority { tags: priority } => rest.sort_by_key(|tag| {
+                priority.iter().position(|priority_tag| priority_tag == *tag).unwrap_or(priority.len())
+            }),
+        }
+
+        sorted.extend(rest.into_iter().cloned());
+        sorted
+    }
+}
+
+impl DatasetImage {
+    // the sorted tags, or None if they already are
+    pub fn sort_tags(&self, sorter: &TagSorter) -> Option<Vec<String>> {
+        let tags = sorter.sort(&self.tags);
+        if tags == self.tags {
+            None
+        } else {
+            Some(tags)
+        }
+    }
+}
+
+impl Dataset {
+    // Sorts the tags of the images in `relative_paths`, or of every image. Only the tags in memory change.
+    // Returns the new dataset and the relative paths of the images whose tags moved.
+    pub fn sort_tags(&self, relative_paths: Option<&[String]>, strategy: &SortStrategy) -> (Dataset, Vec<String>) {
+        let sorter = TagSorter::new(self, strategy);
+        let mut dataset = self.clone();
+        let mut changed = Vec::new();
+
+        for image in dataset.data.iter_mut() {
+            if let Some(relative_paths) = relative_paths {
+                if !relative_paths.contains(&image.relative_path) {
+                    continue;
+                }
+            }
+
+            if let Some(tags) = image.sort_tags(&sorter) {
+                image.tags = tags;
+                changed.push(image.relative_path.clone());
+            }
+        }
+
+        (dataset, changed)
+    }
+}
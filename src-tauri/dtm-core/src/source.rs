@@ -0,0 +1,385 @@
+// Where the captions of a dataset are read from and written back to. Sidecar sources keep a caption file next to every
+// image with the image's name, metadata sources keep the captions of all images in one file at the dataset root:
+//
+//   metadata.jsonl   {"file_name": "10_subject/1.png", "text": "1girl, red hair"}
+//   metadata.csv     file_name,text
+//                    10_subject/1.png,"1girl, red hair"
+//
+// JSON captions, in a `.json` sidecar or a `metadata.jsonl` row, are in the first of the `tags`, `text` and `caption`
+// fields the object has. `tags` can be a list. Everything else in the file is left alone when the caption is written.
+
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+use serde::{ Serialize, Deserialize };
+use serde_json::{Map, Value};
+
+use super::caption::CaptionFormat;
+use super::csv;
+use super::huggingface::METADATA_FILE;
+
+const METADATA_CSV_FILE: &str = "metadata.csv";
+
+// the fields a JSON caption can be in, the first one an object has wins
+const CAPTION_FIELDS: [&str; 3] = ["tags", "text", "caption"];
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum CaptionSource {
+    // `image.txt`
+    Txt,
+    // `image.caption`
+    Caption,
+    // `image.json`
+    Json,
+    // `metadata.jsonl` at the dataset root
+    MetadataJsonl,
+    // `metadata.csv` at the dataset root
+    MetadataCsv,
+}
+
+impl Default for CaptionSource {
+    fn default() -> Self {
+        CaptionSource::Txt
+    }
+}
+
+// A caption as a caption file has it, either the text of the caption or tags that are already split.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Caption {
+    Text(String),
+    Tags(Vec<String>),
+}
+
+impl Caption {
+    pub fn into_tags(self, caption_format: CaptionFormat) -> Vec<String> {
+        match self {
+            Caption::Text(text) => caption_format.split(&text),
+            Caption::Tags(tags) => tags.into_iter().map(|tag| tag.trim().to_string()).filter(|tag| !tag.is_empty()).collect()
+        }
+    }
+
+    // the caption the way a `.txt` caption file in `caption_format` would have it
+    pub fn to_text(&self, caption_format: CaptionFormat) -> String {
+        match self {
+            Caption::Text(text) => text.clone(),
+            Caption::Tags(tags) => caption_format.join(tags)
+        }
+    }
+}
+
+// The captions in a caption file: a sidecar holds the caption of its image, a metadata file the captions of many images.
+pub enum Captions {
+    Sidecar(Caption),
+    // by the relative path of the image, like `DatasetImage::relative_path`
+    Metadata(HashMap<String, Caption>),
+}
+
+impl Captions {
+    pub fn get(&self, relative_path: &str) -> Option<Caption> {
+        match self {
+            Captions::Sidecar(caption) => Some(caption.clone()),
+            Captions::Metadata(captions) => captions.get(relative_path).cloned()
+        }
+    }
+}
+
+// the caption field of a JSON object, and whether it holds a list of tags
+fn caption_field(object: &Map<String, Value>) -> Option<(&'static str, bool)> {
+    CAPTION_FIELDS.iter().find_map(|field| object.get(*field).map(|value| (*field, value.is_array())))
+}
+
+pub fn object_caption(object: &Map<String, Value>) -> Option<Caption> {
+    let (field, _) = caption_field(object)?;
+    match object.get(field) {
+        Some(Value::Array(tags)) => Some(Caption::Tags(tags.iter().filter_map(|tag| tag.as_str()).map(|tag| tag.to_string()).collect())),
+        Some(Value::String(text)) => Some(Caption::Text(text.clone())),
+        _ => Some(Caption::Text(String::new()))
+    }
+}
+
+// Puts the tags in the caption field the object already has, in the same shape, or in the `default` field if it has none.
+fn set_object_caption(object: &mut Map<String, Value>, default: (&str, bool), tags: &[String], caption_format: CaptionFormat) {
+    let (field, as_list) = caption_field(object).unwrap_or(default);
+    let value = if as_list { Value::from(tags.to_vec()) } else { Value::from(caption_format.join(tags)) };
+    object.insert(field.to_string(), value);
+}
+
+fn parse_object(contents: &str) -> Result<Map<String, Value>, String> {
+    match serde_json::from_str::<Value>(contents) {
+        Ok(Value::Object(object)) => Ok(object),
+        Ok(_) => Err("not a JSON object".to_string()),
+        Err(err) => Err(err.to_string())
+    }
+}
+
+// the rows of a `metadata.jsonl`, in order
+fn parse_jsonl(contents: &str) -> Result<Vec<Map<String, Value>>, String> {
+    Ok(parse_jsonl_lines(contents)?.into_iter().filter_map(|line| line.row).collect())
+}
+
+// a line of a `metadata.jsonl` and its row, blank lines have none
+struct JsonlLine<'a> {
+    line: &'a str,
+    row: Option<Map<String, Value>>,
+}
+
+fn parse_jsonl_lines(contents: &str) -> Result<Vec<JsonlLine<'_>>, String> {
+    contents.lines().enumerate()
+        .map(|(index, line)| {
+            if line.trim().is_empty() {
+                return Ok(JsonlLine { line, row: None });
+            }
+            parse_object(line).map(|row| JsonlLine { line, row: Some(row) }).map_err(|err| format!("line {}: {}", index + 1, err))
+        })
+        .collect()
+}
+
+fn row_file_name(row: &Map<String, Value>) -> Option<&str> {
+    row.get("file_name").and_then(|file_name| file_name.as_str())
+}
+
+// a parsed `metadata.csv`, every record after `header` is a row
+struct CsvMetadata<'a> {
+    records: Vec<csv::CsvRecord<'a>>,
+    header: usize,
+    file_name_column: usize,
+    caption_column: Option<usize>,
+}
+
+impl<'a> CsvMetadata<'a> {
+    fn rows(&self) -> impl Iterator<Item = &csv::CsvRecord<'a>> {
+        self.records.iter().skip(self.header + 1).filter(|record| !record.fields.is_empty())
+    }
+}
+
+fn parse_csv(contents: &str) -> Result<CsvMetadata<'_>, String> {
+    let records = csv::parse_records(contents);
+    // the header is the first line that isn't blank
+    let header = records.iter().position(|record| !record.fields.is_empty()).unwrap_or(0);
+    let columns: &[String] = records.get(header).map(|record| record.fields.as_slice()).unwrap_or_default();
+    let file_name_column = match columns.iter().position(|column| column.trim() == "file_name") {
+        Some(column) => column,
+        None => return Err("no file_name column".to_string())
+    };
+    let caption_column = CAPTION_FIELDS.iter().find_map(|field| columns.iter().position(|column| column.trim() == *field));
+
+    Ok(CsvMetadata { records, header, file_name_column, caption_column })
+}
+
+impl CaptionSource {
+    // For a dataset that doesn't declare its source. Every dataset the app opened before has `.txt` sidecars,
+    // so those win, then a metadata file, then whichever of the other sidecars more images have.
+    pub fn detect(root: &Path, image_paths: &[PathBuf]) -> CaptionSource {
+        let sidecars = |extension: &str| image_paths.iter().filter(|image_path| image_path.with_extension(extension).is_file()).count();
+
+        if sidecars("txt") > 0 {
+            CaptionSource::Txt
+        } else if root.join(METADATA_FILE).is_file() {
+            CaptionSource::MetadataJsonl
+        } else if root.join(METADATA_CSV_FILE).is_file() {
+            CaptionSource::MetadataCsv
+        } else {
+            let captions = sidecars("caption");
+            let json = sidecars("json");
+            if captions > 0 && captions >= json {
+                CaptionSource::Caption
+            } else if json > 0 {
+                CaptionSource::Json
+            } else {
+                CaptionSource::Txt
+            }
+        }
+    }
+
+    // metadata sources keep the captions of every image in the same file
+    pub fn is_shared(&self) -> bool {
+        matches!(self, CaptionSource::MetadataJsonl | CaptionSource::MetadataCsv)
+    }
+
+    // A missing `.txt` or `.caption` file is created empty when the dataset is opened, like the app always did.
+    // The other sources get the caption when the image is first saved.
+    pub fn creates_missing(&self) -> bool {
+        matches!(self, CaptionSource::Txt | CaptionSource::Caption)
+    }
+
+    // the caption file of the image at `relative_path`, relative to the dataset root and '/' separated
+    pub fn caption_file(&self, relative_path: &str) -> String {
+        let without_extension = match relative_path.rfind('.') {
+            Some(index) if !relative_path[index..].contains('/') => &relative_path[..index],
+            _ => relative_path
+        };
+
+        match self {
+            CaptionSource::Txt => format!("{}.txt", without_extension),
+            CaptionSource::Caption => format!("{}.caption", without_extension),
+            CaptionSource::Json => format!("{}.json", without_extension),
+            CaptionSource::MetadataJsonl => METADATA_FILE.to_string(),
+            CaptionSource::MetadataCsv => METADATA_CSV_FILE.to_string(),
+        }
+    }
+
+    // same as `caption_file`, as a path inside the dataset at `root`
+    pub fn caption_path(&self, root: &Path, relative_path: &str) -> PathBuf {
+        self.caption_file(relative_path).split('/').fold(root.to_path_buf(), |path, part| path.join(part))
+    }
+
+    pub fn read(&self, contents: &str) -> Result<Captions, String> {
+        match self {
+            CaptionSource::Txt | CaptionSource::Caption => Ok(Captions::Sidecar(Caption::Text(contents.to_string()))),
+            CaptionSource::Json => {
+                let object = parse_object(contents)?;
+                Ok(Captions::Sidecar(object_caption(&object).unwrap_or_else(|| Caption::Text(String::new()))))
+            },
+            CaptionSource::MetadataJsonl => {
+                let captions = parse_jsonl(contents)?.iter().filter_map(|row| {
+                    Some((row_file_name(row)?.to_string(), object_caption(row)?))
+                }).collect();
+                Ok(Captions::Metadata(captions))
+            },
+            CaptionSource::MetadataCsv => {
+                let metadata = parse_csv(contents)?;
+                let captions = metadata.rows().filter_map(|row| {
+                    let file_name = row.fields.get(metadata.file_name_column)?.trim().to_string();
+                    let caption = metadata.caption_column.and_then(|column| row.fields.get(column)).cloned().unwrap_or_default();
+                    Some((file_name, Caption::Text(caption)))
+                }).collect();
+                Ok(Captions::Metadata(captions))
+            },
+        }
+    }
+
+    // The new contents of a caption file (`contents` being what it has now, None if it doesn't exist yet)
+    // with the captions of the images in `captions`, by relative path, set to their tags.
+    // A metadata file gets a row for an image it has no row for yet.
+    pub fn write(&self, contents: Option<&str>, captions: &[(&str, &[String])], caption_format: CaptionFormat) -> Result<String, String> {
+        match self {
+            CaptionSource::Txt | CaptionSource::Caption => {
+                Ok(captions.last().map(|(_, tags)| caption_format.join(tags)).unwrap_or_default())
+            },
+            CaptionSource::Json => {
+                let mut object = match contents {
+                    Some(contents) => parse_object(contents)?,
+                    None => Map::new()
+                };
+                if let Some((_, tags)) = captions.last() {
+                    set_object_caption(&mut object, ("tags", true), tags, caption_format);
+                }
+                serde_json::to_string_pretty(&Value::Object(object)).map(|json| json + "\n").map_err(|err| err.to_string())
+            },
+            CaptionSource::MetadataJsonl => {
+                let lines = parse_jsonl_lines(contents.unwrap_or_default())?;
+                // new rows look like the rows that are there, `text` if there are none
+                let default = lines.iter().filter_map(|line| line.row.as_ref()).find_map(caption_field).unwrap_or(("text", false));
+                let new_captions: HashMap<&str, &[String]> = captions.iter().copied().collect();
+                let mut written: HashSet<&str> = HashSet::new();
+
+                // only the rows of the images in `captions` are written anew, every other line stays exactly as it was
+                let mut output = String::new();
+                for JsonlLine { line, row } in lines {
+                    let edited = match row {
+                        Some(mut row) => match row_file_name(&row).and_then(|file_name| new_captions.get_key_value(file_name)) {
+                            Some((relative_path, tags)) => {
+                                written.insert(*relative_path);
+                                set_object_caption(&mut row, default, tags, caption_format);
+                                Some(Value::Object(row).to_string())
+                            },
+                            None => None
+                        },
+                        None => None
+                    };
+                    output.push_str(edited.as_deref().unwrap_or(line));
+                    output.push('\n');
+                }
+
+                // images without a row get one at the end, in the order of `captions`
+                for (relative_path, _) in captions {
+                    if !written.insert(relative_path) {
+                        continue;
+                    }
+                    let mut row = Map::new();
+                    row.insert("file_name".to_string(), Value::from(relative_path.to_string()));
+                    set_object_caption(&mut row, default, new_captions[relative_path], caption_format);
+                    output.push_str(&Value::Object(row).to_string());
+                    output.push('\n');
+                }
+
+                Ok(output)
+            },
+            CaptionSource::MetadataCsv => {
+                let contents = contents.unwrap_or_default();
+                let CsvMetadata { records, header, file_name_column, caption_column } = if contents.trim().is_empty() {
+                    CsvMetadata { records: Vec::new(), header: 0, file_name_column: 0, caption_column: None }
+                } else {
+                    parse_csv(contents)?
+                };
+                // new and rewritten lines end the way the lines of the file do
+                let line_ending = records.first().map(|record| record.line_ending()).filter(|ending| !ending.is_empty()).unwrap_or("\n");
+                let mut columns = records.get(header).map(|record| record.fields.clone()).unwrap_or_else(|| vec!["file_name".to_string()]);
+                let caption_column = match caption_column {
+                    Some(column) => column,
+                    None => {
+                        columns.push("text".to_string());
+                        columns.len() - 1
+                    }
+                };
+                let new_captions: HashMap<&str, &[String]> = captions.iter().copied().collect();
+                let mut written: HashSet<&str> = HashSet::new();
+
+                // only the header when it gets a caption column and the rows of the images in `captions` are written anew,
+                // every other line stays exactly as it was, line ending and quotes included
+                let mut output = String::new();
+                for (index, record) in records.iter().enumerate() {
+                    let fields = if index == header && columns != record.fields {
+                        Some(columns.clone())
+                    } else if index > header {
+                        match record.fields.get(file_name_column).and_then(|file_name| new_captions.get_key_value(file_name.trim())) {
+                            Some((relative_path, tags)) => {
+                                written.insert(*relative_path);
+                                let mut row = record.fields.clone();
+                                if row.len() <= caption_column {
+                                    row.resize(caption_column + 1, String::new());
+                                }
+                                row[caption_column] = caption_format.join(tags);
+                                Some(row)
+                            },
+                            None => None
+                        }
+                    } else {
+                        None
+                    };
+
+                    match fields {
+                        Some(fields) => {
+                            output.push_str(&csv::write_line(&fields));
+                            output.push_str(match record.line_ending() {
+                                "" => line_ending,
+                                ending => ending
+                            });
+                        },
+                        None => output.push_str(record.raw)
+                    }
+                }
+                if records.is_empty() {
+                    output.push_str(&(csv::write_line(&columns) + line_ending));
+                } else if !output.ends_with('\n') {
+                    output.push_str(line_ending);
+                }
+
+                // images without a row get one at the end, in the order of `captions`
+                for (relative_path, _) in captions {
+                    if !written.insert(relative_path) {
+                        continue;
+                    }
+                    let mut row = vec![String::new(); columns.len()];
+                    row[file_name_column] = relative_path.to_string();
+                    row[caption_column] = caption_format.join(new_captions[relative_path]);
+                    output.push_str(&csv::write_line(&row));
+                    output.push_str(line_ending);
+                }
+
+                Ok(output)
+            },
+        }
+    }
+}
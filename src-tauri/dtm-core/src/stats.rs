@@ -0,0 +1,86 @@
+use std::collections::{HashMap, HashSet};
+
+use serde::{ Serialize, Deserialize };
+
+use super::dataset::Dataset;
+
+// how many of the most used tags go into the co-occurrence matrix when the caller doesn't say
+pub const DEFAULT_CO_OCCURRENCE_TAGS: usize = 20;
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct TagCount {
+    pub tag: String,
+    // number of images with the tag
+    pub count: usize,
+    // count / number of images in the dataset, between 0 and 1
+    pub share: f64,
+}
+
+// `counts[i][j]` is the number of images having both `tags[i]` and `tags[j]`, the diagonal holds the tag counts
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct CoOccurrence {
+    pub tags: Vec<String>,
+    pub counts: Vec<Vec<usize>>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct DatasetStats {
+    pub image_count: usize,
+    // most used first, ties by tag
+    pub tag_counts: Vec<TagCount>,
+    // `tags_per_image[n]` is the number of images with exactly n tags
+    pub tags_per_image: Vec<usize>,
+    pub co_occurrence: CoOccurrence,
+}
+
+impl Dataset {
+    // Tag statistics over the whole dataset. A tag that is on an image twice is only counted once, empty tags aren't counted.
+    // `top_n` is the number of most used tags to build the co-occurrence matrix for.
+    pub fn stats(&self, top_n: usize) -> DatasetStats {
+        let image_tags: Vec<HashSet<&str>> = self.data.iter()
+            .map(|image| image.tags.iter().map(|tag| tag.as_str()).filter(|tag| !tag.is_empty()).collect())
+            .collect();
+
+        let mut counts: HashMap<&str, usize> = HashMap::new();
+        let mut tags_per_image: Vec<usize> = Vec::new();
+        for tags in image_tags.iter() {
+            for tag in tags {
+                *counts.entry(tag).or_insert(0) += 1;
+            }
+
+            if tags_per_image.len() <= tags.len() {
+                tags_per_image.resize(tags.len() + 1, 0);
+            }
+            tags_per_image[tags.len()] += 1;
+        }
+
+        let image_count = self.data.len();
+        let mut tag_counts: Vec<TagCount> = counts.into_iter().map(|(tag, count)| TagCount {
+            tag: tag.to_string(),
+            count,
+            share: count as f64 / image_count as f64,
+        }).collect();
+        tag_counts.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.tag.cmp(&b.tag)));
+
+        let top_tags: Vec<String> = tag_counts.iter().take(top_n).map(|tag_count| tag_count.tag.clone()).collect();
+        let mut co_counts = vec![vec![0; top_tags.len()]; top_tags.len()];
+        for tags in image_tags.iter() {
+            let present: Vec<usize> = top_tags.iter().enumerate()
+                .filter(|(_, tag)| tags.contains(tag.as_str()))
+                .map(|(index, _)| index)
+                .collect();
+            for i in present.iter() {
+                for j in present.iter() {
+                    co_counts[*i][*j] += 1;
+                }
+            }
+        }
+
+        DatasetStats {
+            image_count,
+            tag_counts,
+            tags_per_image,
+            co_occurrence: CoOccurrence { tags: top_tags, counts: co_counts },
+        }
+    }
+}
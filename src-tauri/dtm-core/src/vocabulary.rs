@@ -0,0 +1,191 @@
+use std::collections::{HashMap, HashSet};
+use std::fs::read_to_string;
+use std::io;
+use std::path::Path;
+
+use serde::{ Serialize, Deserialize };
+
+use super::category::TagCategory;
+use super::csv;
+use super::dataset::Dataset;
+
+// how many suggestions autocomplete gives when the caller doesn't say
+pub const DEFAULT_SUGGESTION_LIMIT: usize = 10;
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct VocabularyEntry {
+    pub tag: String,
+    pub category: Option<TagCategory>,
+    // how often the tag is used wherever the vocabulary came from, 0 if it didn't say
+    pub count: u64,
+    // other ways to write the tag, typing one of them suggests the tag
+    pub aliases: Vec<String>,
+}
+
+// The tags we know about besides the ones in the dataset, read from a tag dump or a plain list of tags.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct TagVocabulary {
+    pub entries: Vec<VocabularyEntry>,
+}
+
+impl TagVocabulary {
+    // Reads either a plain list with one tag per line, or a CSV of `tag,category,count,aliases` where
+    // everything after the tag is optional, like the tag dumps of booru sites. A header line is skipped.
+    pub fn parse(contents: &str) -> TagVocabulary {
+        let mut entries = Vec::new();
+        for (index, fields) in csv::parse(contents).into_iter().enumerate() {
+            let tag = fields[0].trim();
+            if tag.is_empty() {
+                continue;
+            }
+
+            let category = fields.get(1).and_then(|category| TagCategory::parse(category));
+            let count = fields.get(2).and_then(|count| count.trim().parse::<u64>().ok());
+            if index == 0 && fields.len() > 1 && category.is_none() && count.is_none() {
+                continue;
+            }
+
+            let aliases = fields.get(3)
+                .map(|aliases| aliases.split(',').map(|alias| alias.trim().to_string()).filter(|alias| !alias.is_empty()).collect())
+                .unwrap_or_default();
+
+            entries.push(VocabularyEntry { tag: tag.to_string(), category, count: count.unwrap_or(0), aliases });
+        }
+
+        TagVocabulary { entries }
+    }
+
+    pub fn load(path: &Path) -> io::Result<TagVocabulary> {
+        read_to_string(path).map(|contents| TagVocabulary::parse(&contents))
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct TagSuggestion {
+    pub tag: String,
+    pub category: Option<TagCategory>,
+    pub vocabulary_count: u64,
+    // number of images in the dataset with the tag
+    pub dataset_count: usize,
+    // how many typos it took to match, 0 for a tag that starts with what was typed
+    pub distance: usize,
+}
+
+// Tags compare without case, and with underscores and spaces being the same, so `red_hair` and `Red Hair` are one tag.
+fn match_key(tag: &str) -> String {
+    tag.to_lowercase().replace('_', " ")
+}
+
+// how many typos we forgive, more for longer input
+fn max_distance(input_length: usize) -> usize {
+    match input_length {
+        0..=2 => 0,
+        3..=5 => 1,
+        _ => 2
+    }
+}
+
+// Optimal string alignment distance: insertions, deletions, substitutions and swapping two neighbours each count as one.
+fn edit_distance(a: &[char], b: &[char]) -> usize {
+    let mut rows = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for (i, row) in rows.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, cell) in rows[0].iter_mut().enumerate() {
+        *cell = j;
+    }
+
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = if a[i - 1] == b[j - 1] { 0 } else { 1 };
+            let mut distance = (rows[i - 1][j] + 1).min(rows[i][j - 1] + 1).min(rows[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                distance = distance.min(rows[i - 2][j - 2] + 1);
+            }
+            rows[i][j] = distance;
+        }
+    }
+
+    rows[a.len()][b.len()]
+}
+
+// How far the typed input is from being the start of the tag, or None if it is too far off.
+// Matching the start of a later word in the tag counts as well, so `hair` finds `red hair`, one step further off.
+// Typos in the first letter aren't forgiven, which keeps this fast enough to run on a whole tag dump for every key press.
+fn prefix_distance(input: &[char], key: &str) -> Option<usize> {
+    let key: Vec<char> = key.chars().collect();
+    let limit = max_distance(input.len());
+
+    let mut best: Option<usize> = None;
+    for start in (0..key.len()).filter(|index| (*index == 0 || key[*index - 1] == ' ') && key[*index] == input[0]) {
+        let word = &key[start..];
+        // the input against the same length of the tag, give or take a character for a missed or an extra one
+        let lengths = input.len().saturating_sub(1)..=(input.len() + 1).min(word.len());
+        let distance = match lengths.map(|length| edit_distance(input, &word[..length])).min() {
+            Some(distance) if distance <= limit => distance + if start == 0 { 0 } else { 1 },
+            _ => continue
+        };
+        best = Some(best.map(|best| best.min(distance)).unwrap_or(distance));
+    }
+
+    best
+}
+
+// Suggests tags for what the user typed so far, from the vocabulary and the tags already in the dataset.
+// Closer matches come first, then tags used more in the dataset, then tags used more according to the vocabulary.
+pub fn autocomplete(vocabulary: &TagVocabulary, dataset: Option<&Dataset>, input: &str, limit: usize) -> Vec<TagSuggestion> {
+    let input: Vec<char> = match_key(input.trim()).chars().collect();
+    if input.is_empty() {
+        return Vec::new();
+    }
+
+    // the tags of the dataset by match key, with the spelling the captions use and the number of images using them
+    let mut dataset_tags: HashMap<String, (&String, usize)> = HashMap::new();
+    if let Some(dataset) = dataset {
+        for image in dataset.data.iter() {
+            // an image counts once for a tag, even if it has the tag twice or spelled two ways
+            let mut counted: HashSet<String> = HashSet::new();
+            for tag in image.tags.iter().filter(|tag| !tag.is_empty()) {
+                let key = match_key(tag);
+                if counted.insert(key.clone()) {
+                    dataset_tags.entry(key).or_insert((tag, 0)).1 += 1;
+                }
+            }
+        }
+    }
+
+    let mut suggestions: HashMap<String, TagSuggestion> = HashMap::new();
+    for entry in vocabulary.entries.iter() {
+        let key = match_key(&entry.tag);
+        // typing an alias suggests the tag it stands for, as if the tag itself matched
+        let distance = std::iter::once(prefix_distance(&input, &key))
+            .chain(entry.aliases.iter().map(|alias| prefix_distance(&input, &match_key(alias))))
+            .flatten()
+            .min();
+
+        if let Some(distance) = distance {
+            // the dataset's spelling wins, so suggestions match the captions
+            let (tag, dataset_count) = dataset_tags.get(&key).map(|(tag, count)| ((*tag).clone(), *count)).unwrap_or_else(|| (entry.tag.clone(), 0));
+            suggestions.entry(key).or_insert(TagSuggestion { tag, category: entry.category, vocabulary_count: entry.count, dataset_count, distance });
+        }
+    }
+    for (key, (tag, count)) in dataset_tags.iter() {
+        if suggestions.contains_key(key) {
+            continue;
+        }
+        if let Some(distance) = prefix_distance(&input, key) {
+            let category = dataset.map(|dataset| dataset.categories.get(tag));
+            suggestions.insert(key.clone(), TagSuggestion { tag: (*tag).clone(), category, vocabulary_count: 0, dataset_count: *count, distance });
+        }
+    }
+
+    let mut suggestions: Vec<TagSuggestion> = suggestions.into_values().collect();
+    suggestions.sort_by(|a, b| {
+        a.distance.cmp(&b.distance)
+            .then_with(|| b.dataset_count.cmp(&a.dataset_count))
+            .then_with(|| b.vocabulary_count.cmp(&a.vocabulary_count))
+            .then_with(|| a.tag.cmp(&b.tag))
+    });
+    suggestions.truncate(limit);
+    suggestions
+}
@@ -0,0 +1,221 @@
+use std::collections::HashMap;
+use std::collections::hash_map::DefaultHasher;
+use std::fs::{metadata, read_to_string, write};
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use serde::{ Serialize, Deserialize };
+
+use super::conflict::CaptionConflict;
+use super::dataset::{find_image_files, Dataset, DatasetError, DatasetErrorType, DatasetImage};
+use super::source::{Caption, CaptionSource};
+
+// How a single file looked at the time of a scan. A rename keeps both, so a renamed image can be told apart from a new one.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct FileStamp {
+    modified: Option<SystemTime>,
+    len: u64,
+}
+
+impl FileStamp {
+    fn of(path: &Path) -> Option<FileStamp> {
+        let metadata = metadata(path).ok()?;
+        Some(FileStamp { modified: metadata.modified().ok(), len: metadata.len() })
+    }
+}
+
+// How the caption of an image looked at the time of a scan. In a metadata file that is the image's own row,
+// so a change to the file only counts for the images whose rows changed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum CaptionStamp {
+    File(Option<FileStamp>),
+    Row(u64),
+}
+
+impl CaptionStamp {
+    fn of_row(caption: Option<Caption>) -> CaptionStamp {
+        let mut hasher = DefaultHasher::new();
+        caption.hash(&mut hasher);
+        CaptionStamp::Row(hasher.finish())
+    }
+}
+
+// Every image in a dataset and its caption, keyed by the image's path relative to the dataset root.
+#[derive(Default, Debug)]
+pub struct DatasetScan {
+    images: HashMap<String, (PathBuf, FileStamp, CaptionStamp)>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum DatasetChange {
+    ImageAdded { path: PathBuf },
+    ImageRemoved { relative_path: String },
+    ImageRenamed { from: String, to: PathBuf },
+    // the caption file of an image changed, whether that was us or someone else is up to the caller
+    CaptionChanged { relative_path: String },
+}
+
+impl DatasetScan {
+    pub fn new(root: &Path, max_depth: usize, caption_source: CaptionSource) -> Result<DatasetScan, DatasetError> {
+        let mut images = HashMap::new();
+
+        // a metadata file we can't read right now counts as having no rows
+        let metadata = if caption_source.is_shared() {
+            let contents = read_to_string(caption_source.caption_path(root, "")).unwrap_or_default();
+            caption_source.read(&contents).ok()
+        } else {
+            None
+        };
+
+        for image_path in find_image_files(root, max_depth)? {
+            let image = DatasetImage::from_image_path(root, &image_path, caption_source)?;
+            let image_stamp = match FileStamp::of(&image_path) {
+                Some(image_stamp) => image_stamp,
+                // the image went away between listing and looking at it, the next scan picks that up
+                None => continue
+            };
+            let caption_stamp = match (caption_source.is_shared(), &metadata) {
+                (false, _) => CaptionStamp::File(FileStamp::of(&image.caption_path)),
+                (true, Some(captions)) => CaptionStamp::of_row(captions.get(&image.relative_path)),
+                (true, None) => CaptionStamp::of_row(None)
+            };
+
+            images.insert(image.relative_path, (image_path, image_stamp, caption_stamp));
+        }
+
+        Ok(DatasetScan { images })
+    }
+
+    // what happened between `self` and the newer scan `next`
+    pub fn changes(&self, next: &DatasetScan) -> Vec<DatasetChange> {
+        let mut changes = Vec::new();
+
+        let mut removed: Vec<(&String, &FileStamp)> = self.images.iter()
+            .filter(|(relative_path, _)| !next.images.contains_key(*relative_path))
+            .map(|(relative_path, (_, image_stamp, _))| (relative_path, image_stamp))
+            .collect();
+        removed.sort_by(|a, b| a.0.cmp(b.0));
+
+        let mut added: Vec<(&String, &PathBuf, &FileStamp)> = next.images.iter()
+            .filter(|(relative_path, _)| !self.images.contains_key(*relative_path))
+            .map(|(relative_path, (path, image_stamp, _))| (relative_path, path, image_stamp))
+            .collect();
+        added.sort_by(|a, b| a.0.cmp(b.0));
+
+        // an image that disappeared while one with the exact same size and modification time showed up was renamed
+        for (relative_path, image_stamp) in removed {
+            match added.iter().position(|(_, _, added_stamp)| *added_stamp == image_stamp) {
+                Some(index) => {
+                    let (_, path, _) = added.remove(index);
+                    changes.push(DatasetChange::ImageRenamed { from: relative_path.clone(), to: path.clone() });
+                },
+                None => changes.push(DatasetChange::ImageRemoved { relative_path: relative_path.clone() })
+            }
+        }
+
+        for (_, path, _) in added {
+            changes.push(DatasetChange::ImageAdded { path: path.clone() });
+        }
+
+        let mut changed_captions: Vec<&String> = self.images.iter().filter_map(|(relative_path, (_, _, caption_stamp))| {
+            let (_, _, next_caption_stamp) = next.images.get(relative_path)?;
+            if caption_stamp == next_caption_stamp {
+                return None;
+            }
+            Some(relative_path)
+        }).collect();
+        changed_captions.sort();
+
+        for relative_path in changed_captions {
+            changes.push(DatasetChange::CaptionChanged { relative_path: relative_path.clone() });
+        }
+
+        changes
+    }
+}
+
+// What the frontend gets told after a change was applied to the open dataset.
+#[derive(Clone, Debug)]
+pub enum WatcherEvent {
+    ImageAdded(DatasetImage),
+    // the relative path of the image that is gone
+    ImageRemoved(String),
+    // `from` is the relative path the image had before
+    ImageRenamed { from: String, image: DatasetImage },
+    TagsChangedExternally(DatasetImage),
+    // the caption changed on disk while the image has unsaved edits, we keep ours until the user decides
+    Conflict(CaptionConflict),
+}
+
+// Reads the caption of an image, a missing caption means no tags yet. With `create_missing`, a missing `.txt` or
+// `.caption` file is created and the image starts out with one empty tag, the way loading the dataset does it.
+fn read_image(dataset: &Dataset, image_path: &Path, create_missing: bool) -> Result<DatasetImage, DatasetError> {
+    let mut image = DatasetImage::from_image_path(Path::new(&dataset.path), image_path, dataset.caption_source)?;
+    let created = create_missing && dataset.caption_source.creates_missing() && !image.caption_path.exists();
+    if created && write(&image.caption_path, "").is_err() {
+        return Err(DatasetError::new(DatasetErrorType::Write, Some(image.caption_path.to_string_lossy().to_string())));
+    }
+    let (tags, caption, file_hash) = dataset.read_captions(&[&image])?.remove(0);
+    image.tags = if created { vec!["".to_string()] } else { tags };
+    image.record_original(caption, file_hash);
+    Ok(image)
+}
+
+// Brings the dataset in line with a change seen on disk. Returns None if there is nothing to tell the frontend,
+// like for a caption we wrote ourselves.
+pub fn apply_change(dataset: &mut Dataset, change: &DatasetChange) -> Option<WatcherEvent> {
+    match change {
+        DatasetChange::ImageAdded { path } => {
+            let image = read_image(dataset, path, true).ok()?;
+            if dataset.data.iter().any(|dataset_image| dataset_image.relative_path == image.relative_path) {
+                return None;
+            }
+            dataset.data.push(image.clone());
+            Some(WatcherEvent::ImageAdded(image))
+        },
+        DatasetChange::ImageRemoved { relative_path } => {
+            let index = dataset.data.iter().position(|image| &image.relative_path == relative_path)?;
+            // unsaved edits aren't dropped behind the user's back. the image stays, with its edits and their history,
+            // until the conflict is settled. its caption counts as deleted, taking theirs drops the image.
+            if dataset.data[index].has_changed_tags() {
+                let image = &dataset.data[index];
+                return Some(WatcherEvent::Conflict(CaptionConflict {
+                    relative_path: image.relative_path.clone(),
+                    caption_path: image.caption_path.to_string_lossy().to_string(),
+                    mine: image.tags.clone(),
+                    theirs: Vec::new(),
+                }));
+            }
+            let image = dataset.data.remove(index);
+            Some(WatcherEvent::ImageRemoved(image.relative_path))
+        },
+        DatasetChange::ImageRenamed { from, to } => {
+            let index = dataset.data.iter().position(|image| &image.relative_path == from)?;
+            let mut image = read_image(dataset, to, true).ok()?;
+            // unsaved edits move along with the image
+            if dataset.data[index].has_changed_tags() {
+                image.tags = dataset.data[index].tags.clone();
+            }
+            let from = std::mem::replace(&mut dataset.data[index], image.clone()).relative_path;
+            Some(WatcherEvent::ImageRenamed { from, image })
+        },
+        DatasetChange::CaptionChanged { relative_path } => {
+            let index = dataset.data.iter().position(|image| &image.relative_path == relative_path)?;
+            if !dataset.captions_changed_externally(&[&dataset.data[index]]) {
+                return None;
+            }
+
+            if dataset.data[index].has_changed_tags() {
+                let image = dataset.data[index].clone();
+                let conflict = dataset.caption_conflicts(Some(&image)).into_iter().find(|conflict| conflict.relative_path == image.relative_path)?;
+                return Some(WatcherEvent::Conflict(conflict));
+            }
+
+            let image = read_image(dataset, Path::new(&dataset.data[index].path), false).ok()?;
+            dataset.data[index] = image.clone();
+            Some(WatcherEvent::TagsChangedExternally(image))
+        },
+    }
+}
@@ -0,0 +1,229 @@
+// WebDataset's tar shards: the images packed into numbered `.tar` files together with their captions, for training
+// runs that stream their data instead of reading a folder. The files of a sample share a key and differ in their
+// extension, so a shard holds
+//
+//   00000000.png  00000000.txt  00000001.jpg  00000001.txt  ...
+//
+// `shardindex.json` next to the shards lists them with how many samples each has, the index WebDataset's `wids` reads.
+
+use std::collections::BTreeMap;
+use std::fs::{create_dir_all, metadata, read, File};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use serde::{ Serialize, Deserialize };
+use serde_json::json;
+
+use super::category::TagCategory;
+use super::dataset::{Dataset, DatasetError, DatasetErrorType, DatasetImage};
+use super::file::{resolve, write_atomic};
+use super::pinned::pin_tags;
+
+pub const SHARD_INDEX_FILE: &str = "shardindex.json";
+pub const DEFAULT_SHARD_SAMPLES: usize = 1000;
+
+// tar files are made of 512 byte blocks, and end with two empty ones
+const BLOCK: usize = 512;
+const END_OF_ARCHIVE: u64 = 2 * BLOCK as u64;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ShardCaption {
+    // `key.txt`, the caption the way a caption file has it
+    Txt,
+    // `key.json`, with the tags as a list and grouped by category
+    Json,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct WebDatasetExportOptions {
+    pub caption: ShardCaption,
+    // a shard is full once it has this many samples
+    pub max_samples: usize,
+    // or once the next sample would make it bigger than this, a shard always gets at least one sample
+    pub max_bytes: Option<u64>,
+}
+
+impl Default for WebDatasetExportOptions {
+    fn default() -> Self {
+        WebDatasetExportOptions { caption: ShardCaption::Txt, max_samples: DEFAULT_SHARD_SAMPLES, max_bytes: None }
+    }
+}
+
+// a shard in the index, `url` is relative to the index file
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct ShardInfo {
+    pub url: String,
+    pub nsamples: usize,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ShardIndex {
+    #[serde(rename = "__kind__")]
+    pub kind: String,
+    pub wids_version: u32,
+    pub name: String,
+    pub shardlist: Vec<ShardInfo>,
+}
+
+impl ShardIndex {
+    pub fn samples(&self) -> usize {
+        self.shardlist.iter().map(|shard| shard.nsamples).sum()
+    }
+}
+
+// the ustar header of a regular file. names are sample keys, which always fit the 100 bytes a name gets
+fn tar_header(name: &str, size: u64, modified: u64) -> [u8; BLOCK] {
+    let mut header = [0u8; BLOCK];
+    let mut field = |offset: usize, value: &[u8]| header[offset..offset + value.len()].copy_from_slice(value);
+    field(0, name.as_bytes());
+    field(100, b"0000644\0");
+    field(108, b"0000000\0");
+    field(116, b"0000000\0");
+    field(124, format!("{:011o}\0", size).as_bytes());
+    field(136, format!("{:011o}\0", modified).as_bytes());
+    // the checksum is taken with its own field as spaces
+    field(148, b"        ");
+    field(156, b"0");
+    field(257, b"ustar\0");
+    field(263, b"00");
+
+    let checksum: u32 = header.iter().map(|byte| *byte as u32).sum();
+    header[148..156].copy_from_slice(format!("{:06o}\0 ", checksum).as_bytes());
+    header
+}
+
+// how much room a file takes in a tar, its header and its contents padded to whole blocks
+fn tar_entry_size(size: usize) -> u64 {
+    (BLOCK + (size + BLOCK - 1) / BLOCK * BLOCK) as u64
+}
+
+struct Shard {
+    file: BufWriter<File>,
+    samples: usize,
+    bytes: u64,
+}
+
+// Writes the samples into shards in `output`, starting the next shard whenever one is full.
+struct ShardWriter<'a> {
+    output: &'a Path,
+    options: &'a WebDatasetExportOptions,
+    current: Option<Shard>,
+    shardlist: Vec<ShardInfo>,
+}
+
+impl<'a> ShardWriter<'a> {
+    fn shard_path(&self, index: usize) -> PathBuf {
+        self.output.join(format!("shard-{:06}.tar", index))
+    }
+
+    fn is_full(&self, sample_bytes: u64) -> bool {
+        match &self.current {
+            Some(shard) => {
+                shard.samples >= self.options.max_samples.max(1)
+                    || self.options.max_bytes.map_or(false, |max_bytes| shard.bytes + sample_bytes + END_OF_ARCHIVE > max_bytes)
+            },
+            None => true
+        }
+    }
+
+    fn finish_shard(&mut self) -> io::Result<()> {
+        if let Some(mut shard) = self.current.take() {
+            shard.file.write_all(&[0u8; END_OF_ARCHIVE as usize])?;
+            shard.file.into_inner().map_err(|err| err.into_error())?.sync_all()?;
+            let url = format!("shard-{:06}.tar", self.shardlist.len());
+            self.shardlist.push(ShardInfo { url, nsamples: shard.samples });
+        }
+        Ok(())
+    }
+
+    // the files of one sample, as names and contents
+    fn add(&mut self, files: &[(String, &[u8])], modified: u64) -> io::Result<()> {
+        let sample_bytes = files.iter().map(|(_, contents)| tar_entry_size(contents.len())).sum();
+        if self.is_full(sample_bytes) {
+            self.finish_shard()?;
+            let file = File::create(self.shard_path(self.shardlist.len()))?;
+            self.current = Some(Shard { file: BufWriter::new(file), samples: 0, bytes: 0 });
+        }
+
+        let shard = match &mut self.current {
+            Some(shard) => shard,
+            None => return Err(io::Error::new(io::ErrorKind::Other, "no shard to write to"))
+        };
+        for (name, contents) in files {
+            shard.file.write_all(&tar_header(name, contents.len() as u64, modified))?;
+            shard.file.write_all(contents)?;
+            let padding = tar_entry_size(contents.len()) as usize - BLOCK - contents.len();
+            shard.file.write_all(&[0u8; BLOCK][..padding])?;
+        }
+        shard.samples += 1;
+        shard.bytes += sample_bytes;
+        Ok(())
+    }
+
+    fn finish(mut self) -> io::Result<Vec<ShardInfo>> {
+        self.finish_shard()?;
+        Ok(self.shardlist)
+    }
+}
+
+impl Dataset {
+    // the `.json` caption of a sample
+    fn sample_json(&self, image: &DatasetImage, tags: &[String]) -> String {
+        let mut by_category: BTreeMap<TagCategory, Vec<&String>> = BTreeMap::new();
+        for tag in tags {
+            by_category.entry(self.categories.get(tag)).or_default().push(tag);
+        }
+
+        json!({
+            "file_name": image.relative_path,
+            "text": self.caption_format.join(tags),
+            "tags": tags,
+            "tags_by_category": by_category,
+        }).to_string()
+    }
+
+    // Packs the images and their captions into tar shards in `output`, and writes the shard index next to them.
+    // The captions are the tags in memory with the pinned tags in front, like the Hugging Face export.
+    // The keys are the positions of the images in the dataset, so a sample can be found by `file_name` in its JSON caption.
+    // `output` can't be inside the dataset, for the same reason as with `export_huggingface`.
+    pub fn export_webdataset(&self, output: &Path, options: &WebDatasetExportOptions) -> Result<ShardIndex, DatasetError> {
+        if resolve(output).starts_with(resolve(Path::new(&self.path))) {
+            return Err(DatasetError::new(DatasetErrorType::Export, Some(output.to_string_lossy().to_string())));
+        }
+        let write_error = |path: &Path| DatasetError::new(DatasetErrorType::Write, Some(path.to_string_lossy().to_string()));
+        create_dir_all(output).map_err(|_| write_error(output))?;
+
+        let mut writer = ShardWriter { output, options, current: None, shardlist: Vec::new() };
+        for (index, image) in self.data.iter().enumerate() {
+            let image_path = Path::new(&image.path);
+            let image_contents = read(image_path).map_err(|_| DatasetError::new(DatasetErrorType::Read, Some(image.path.clone())))?;
+            let modified = metadata(image_path).and_then(|metadata| metadata.modified()).ok()
+                .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+                .map(|modified| modified.as_secs())
+                .unwrap_or(0);
+
+            // WebDataset takes everything after the first dot of a name as the extension
+            let key = format!("{:08}", index);
+            let extension = image_path.extension().map(|extension| extension.to_string_lossy().to_lowercase()).unwrap_or_default();
+            let tags: Vec<String> = pin_tags(&self.settings.pinned_tags, &image.tags).into_iter().filter(|tag| !tag.is_empty()).collect();
+            let caption = match options.caption {
+                ShardCaption::Txt => (format!("{}.txt", key), self.caption_format.join(&tags)),
+                ShardCaption::Json => (format!("{}.json", key), self.sample_json(image, &tags)),
+            };
+
+            let files = [(format!("{}.{}", key, extension), image_contents.as_slice()), (caption.0, caption.1.as_bytes())];
+            writer.add(&files, modified).map_err(|_| write_error(&writer.shard_path(writer.shardlist.len())))?;
+        }
+        let shardlist = writer.finish().map_err(|_| write_error(output))?;
+
+        let index = ShardIndex { kind: "wids-shard-index-v1".to_string(), wids_version: 1, name: self.name.clone(), shardlist };
+        let index_path = output.join(SHARD_INDEX_FILE);
+        let contents = serde_json::to_string_pretty(&index).map_err(|_| write_error(&index_path))?;
+        write_atomic(&index_path, contents.as_bytes()).map_err(|_| write_error(&index_path))?;
+
+        Ok(index)
+    }
+}
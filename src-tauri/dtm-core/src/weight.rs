@@ -0,0 +1,289 @@
+// Prompt emphasis syntax as used by most Stable Diffusion UIs and trainers:
+//
+//   (masterpiece:1.2)   explicit weight, `(masterpiece: 1.2)` too
+//   ((masterpiece))     every pair of parentheses multiplies the weight by 1.1
+//   [background]        every pair of brackets divides the weight by 1.1
+//   \(artist\)          escaped parentheses are part of the tag, not emphasis
+//
+// Tags are still stored as the strings found in the caption file. `WeightedTag` is the structured view of one,
+// and `WeightedTag::parse(tag).to_string() == tag` holds for every tag, whatever it contains.
+
+use serde::{ Serialize, Deserialize };
+
+use super::dataset::{Dataset, DatasetError, DatasetErrorType};
+
+const EMPHASIS_FACTOR: f64 = 1.1;
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum WeightSyntax {
+    Plain,
+    // the weight as written, so "1.20" stays "1.20", and the spaces around the colon, so `(text : 1.2)` stays as it is
+    Explicit {
+        weight: String,
+        #[serde(default)]
+        before_colon: String,
+        #[serde(default)]
+        after_colon: String,
+    },
+    Parentheses { depth: usize },
+    Brackets { depth: usize },
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct WeightedTag {
+    // the tag without emphasis and escapes, "(artist)" for `\(artist\)`
+    pub text: String,
+    // the tag without emphasis, escapes kept as written
+    pub escaped: String,
+    // None for a tag without emphasis
+    pub weight: Option<f64>,
+    pub syntax: WeightSyntax,
+}
+
+impl WeightedTag {
+    pub fn parse(tag: &str) -> WeightedTag {
+        let (escaped, syntax) = parse_syntax(tag);
+        let weight = match &syntax {
+            WeightSyntax::Plain => None,
+            WeightSyntax::Explicit { weight, .. } => weight.parse::<f64>().ok(),
+            WeightSyntax::Parentheses { depth } => Some(EMPHASIS_FACTOR.powi(*depth as i32)),
+            WeightSyntax::Brackets { depth } => Some(1.0 / EMPHASIS_FACTOR.powi(*depth as i32)),
+        };
+
+        WeightedTag { text: unescape(escaped), escaped: escaped.to_string(), weight, syntax }
+    }
+
+    // Sets an explicit weight, or removes the emphasis with None. Other emphasis syntax is replaced,
+    // an explicit weight keeps the spaces it had around the colon.
+    pub fn with_weight(&self, weight: Option<f64>) -> Result<WeightedTag, DatasetError> {
+        let syntax = match (weight, &self.syntax) {
+            (Some(weight), WeightSyntax::Explicit { before_colon, after_colon, .. }) => WeightSyntax::Explicit {
+                weight: format_weight(weight)?,
+                before_colon: before_colon.clone(),
+                after_colon: after_colon.clone(),
+            },
+            (Some(weight), _) => WeightSyntax::Explicit { weight: format_weight(weight)?, before_colon: String::new(), after_colon: String::new() },
+            (None, _) => WeightSyntax::Plain
+        };
+        Ok(WeightedTag { text: self.text.clone(), escaped: self.escaped.clone(), weight, syntax })
+    }
+}
+
+impl std::fmt::Display for WeightedTag {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match &self.syntax {
+            WeightSyntax::Plain => write!(f, "{}", self.escaped),
+            WeightSyntax::Explicit { weight, before_colon, after_colon } => write!(f, "({}{}:{}{})", self.escaped, before_colon, after_colon, weight),
+            WeightSyntax::Parentheses { depth } => write!(f, "{}{}{}", "(".repeat(*depth), self.escaped, ")".repeat(*depth)),
+            WeightSyntax::Brackets { depth } => write!(f, "{}{}{}", "[".repeat(*depth), self.escaped, "]".repeat(*depth)),
+        }
+    }
+}
+
+// at most two decimals, without trailing zeros. 1.0 -> "1", 1.25 -> "1.25", 0.9 -> "0.9".
+// a weight has to be a number above 0, and one that still is with two decimals
+fn format_weight(weight: f64) -> Result<String, DatasetError> {
+    let formatted = format!("{:.2}", weight);
+    if !weight.is_finite() || formatted.parse::<f64>().map_or(true, |rounded| rounded <= 0.0) {
+        return Err(DatasetError::new(DatasetErrorType::Weight, Some(weight.to_string())));
+    }
+    Ok(formatted.trim_end_matches('0').trim_end_matches('.').to_string())
+}
+
+// removes the backslash from every escaped character
+pub fn unescape(text: &str) -> String {
+    let mut unescaped = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            match chars.next() {
+                Some(next) => unescaped.push(next),
+                None => unescaped.push(c)
+            }
+        } else {
+            unescaped.push(c);
+        }
+    }
+    unescaped
+}
+
+// Byte index of the bracket closing the one at `open`, skipping escaped characters and nested brackets.
+fn closing_index(text: &str, open: usize) -> Option<usize> {
+    let bytes = text.as_bytes();
+    let (open_char, close_char) = match bytes[open] {
+        b'(' => (b'(', b')'),
+        b'[' => (b'[', b']'),
+        _ => return None
+    };
+
+    let mut depth = 0;
+    let mut index = open;
+    while index < bytes.len() {
+        match bytes[index] {
+            b'\\' => index += 1,
+            c if c == open_char => depth += 1,
+            c if c == close_char => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(index);
+                }
+            },
+            _ => {}
+        }
+        index += 1;
+    }
+    None
+}
+
+// whether the whole text is wrapped in one pair of brackets, e.g. `(a) (b)` isn't
+fn is_wrapped(text: &str, open: u8) -> bool {
+    text.len() >= 2 && text.as_bytes()[0] == open && closing_index(text, 0) == Some(text.len() - 1)
+}
+
+// Splits a tag into its escaped text and the emphasis around it.
+// Anything that doesn't read as emphasis, like unbalanced brackets in `smile :)`, is plain text.
+fn parse_syntax(tag: &str) -> (&str, WeightSyntax) {
+    if is_wrapped(tag, b'(') {
+        let inner = &tag[1..tag.len() - 1];
+
+        // (text:1.2), the weight is whatever follows the last colon outside of any brackets.
+        // spaces around the colon, like in `(text: 1.2)`, belong to neither side
+        if let Some(colon) = top_level_colon(inner) {
+            let text = inner[..colon].trim_end();
+            let weight = inner[colon + 1..].trim_start();
+            if !text.is_empty() && weight.parse::<f64>().is_ok() {
+                return (text, WeightSyntax::Explicit {
+                    weight: weight.to_string(),
+                    before_colon: inner[text.len()..colon].to_string(),
+                    after_colon: inner[colon + 1..inner.len() - weight.len()].to_string(),
+                });
+            }
+        }
+
+        let mut depth = 1;
+        let mut inner = inner;
+        while is_wrapped(inner, b'(') {
+            depth += 1;
+            inner = &inner[1..inner.len() - 1];
+        }
+        if !inner.is_empty() {
+            return (inner, WeightSyntax::Parentheses { depth });
+        }
+    }
+
+    if is_wrapped(tag, b'[') {
+        let mut depth = 1;
+        let mut inner = &tag[1..tag.len() - 1];
+        while is_wrapped(inner, b'[') {
+            depth += 1;
+            inner = &inner[1..inner.len() - 1];
+        }
+        if !inner.is_empty() {
+            return (inner, WeightSyntax::Brackets { depth });
+        }
+    }
+
+    (tag, WeightSyntax::Plain)
+}
+
+fn top_level_colon(text: &str) -> Option<usize> {
+    let bytes = text.as_bytes();
+    let mut depth: i32 = 0;
+    let mut colon = None;
+    let mut index = 0;
+    while index < bytes.len() {
+        match bytes[index] {
+            b'\\' => index += 1,
+            b'(' | b'[' => depth += 1,
+            b')' | b']' => depth -= 1,
+            b':' if depth == 0 => colon = Some(index),
+            _ => {}
+        }
+        index += 1;
+    }
+    colon
+}
+
+// Splits a caption on `separator`, except where it is escaped or inside brackets,
+// so "(masterpiece:1.2). a cat" splits into two tags on periods.
+pub fn split_outside_emphasis(contents: &str, separator: char) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut depth: i32 = 0;
+    let mut start = 0;
+    let mut escaped = false;
+    for (index, c) in contents.char_indices() {
+        if escaped {
+            escaped = false;
+            continue;
+        }
+        match c {
+            '\\' => escaped = true,
+            '(' | '[' => depth += 1,
+            // unbalanced closing brackets like in `smile :)` don't make the rest of the caption unsplittable
+            ')' | ']' => depth = (depth - 1).max(0),
+            c if c == separator && depth == 0 => {
+                parts.push(&contents[start..index]);
+                start = index + c.len_utf8();
+            },
+            _ => {}
+        }
+    }
+    parts.push(&contents[start..]);
+
+    // an unbalanced opening bracket would swallow the rest of the caption, so it falls back to a plain split
+    if depth > 0 {
+        return contents.split(separator).collect();
+    }
+    parts
+}
+
+impl Dataset {
+    // Sets the weight of a tag on an image, or removes its emphasis with None. Only the tags in memory change.
+    pub fn set_tag_weight(&self, relative_path: &str, tag: &str, weight: Option<f64>) -> Result<Dataset, DatasetError> {
+        let mut dataset = self.clone();
+
+        // the frontend only knows about images and tags we gave it
+        let image = match dataset.data.iter_mut().find(|image| image.relative_path == relative_path) {
+            Some(image) => image,
+            None => return Err(DatasetError::new(DatasetErrorType::ShouldBeImpossible, None))
+        };
+        let existing = match image.tags.iter_mut().find(|existing| *existing == tag) {
+            Some(existing) => existing,
+            None => return Err(DatasetError::new(DatasetErrorType::ShouldBeImpossible, None))
+        };
+
+        *existing = WeightedTag::parse(tag).with_weight(weight)?.to_string();
+        Ok(dataset)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn explicit_weight_with_spaces_around_the_colon() {
+        for tag in ["(red hair:1.2)", "(red hair: 1.2)", "(red hair :1.2)", "(red hair : 1.2)"] {
+            let weighted = WeightedTag::parse(tag);
+            assert_eq!(weighted.text, "red hair", "{}", tag);
+            assert_eq!(weighted.weight, Some(1.2), "{}", tag);
+            assert_eq!(weighted.to_string(), tag);
+        }
+    }
+
+    #[test]
+    fn new_weight_keeps_the_spaces_around_the_colon() {
+        let weighted = WeightedTag::parse("(red hair : 1.2)").with_weight(Some(0.8)).unwrap();
+        assert_eq!(weighted.to_string(), "(red hair : 0.8)");
+        assert_eq!(WeightedTag::parse("red hair").with_weight(Some(1.5)).unwrap().to_string(), "(red hair:1.5)");
+    }
+
+    #[test]
+    fn weight_has_to_be_a_number_above_zero() {
+        let weighted = WeightedTag::parse("red hair");
+        for weight in [0.0, -1.0, 0.001, f64::NAN, f64::INFINITY] {
+            assert!(weighted.with_weight(Some(weight)).is_err(), "{}", weight);
+        }
+    }
+}
@@ -0,0 +1,61 @@
+use std::path::Path;
+
+use tauri::State;
+
+use dtm_core::{backup::{list_snapshots, BackupRetention, BackupSnapshot}, history::TagOperation, logger::Logger};
+use crate::state::{self, DatasetState};
+
+
+#[tauri::command]
+pub fn list_dataset_backups(state: State<DatasetState>) -> Vec<BackupSnapshot> {
+    let dataset = state.dataset.lock().unwrap();
+    match &*dataset {
+        Some(dataset) => list_snapshots(Path::new(&dataset.path)),
+        None => Vec::new()
+    }
+}
+
+// restores every image in the snapshot when `relative_paths` is None
+#[tauri::command]
+pub fn restore_dataset_backup(id: String, relative_paths: Option<Vec<String>>, state: State<DatasetState>, window: tauri::Window) -> bool {
+    let mut dataset = state.dataset.lock().unwrap();
+    if let Some(dataset) = &mut *dataset {
+        match dataset.restore_backup(&id, relative_paths.as_deref()) {
+            Ok((new, restored_images)) => {
+                state.record_history(TagOperation::Replace, dataset, &new);
+                *dataset = new;
+
+                let relative_paths: Vec<String> = restored_images.into_iter().map(|image| image.relative_path).collect();
+                state::emit_updated_images(&window, dataset, &relative_paths);
+                state::sync_dirty_state(&window, dataset);
+                Logger::info(&format!("Restored {} images from backup '{}'", relative_paths.len(), id));
+                true
+            },
+            Err(err) => {
+                Logger::error(&format!("Could not restore backup '{}': {}", id, err));
+                false
+            }
+        }
+    } else {
+        Logger::error(&format!("Could not restore backup '{}': dataset is None", id));
+        false
+    }
+}
+
+#[tauri::command]
+pub fn set_backup_retention(retention: BackupRetention, state: State<DatasetState>) -> bool {
+    let mut dataset = state.dataset.lock().unwrap();
+    if let Some(dataset) = &mut *dataset {
+        dataset.settings.backup_retention = retention;
+        match dataset.settings.save(Path::new(&dataset.path)) {
+            Ok(_) => true,
+            Err(err) => {
+                Logger::error(&format!("Could not save backup retention: {}", err));
+                false
+            }
+        }
+    } else {
+        Logger::error("Could not set backup retention: dataset is None");
+        false
+    }
+}
@@ -0,0 +1,76 @@
+use std::collections::HashMap;
+use std::fs::read_to_string;
+use std::path::Path;
+use std::sync::Arc;
+
+use tauri::State;
+
+use dtm_core::{category::TagCategory, dataset::Dataset, logger::Logger};
+use crate::state::DatasetState;
+
+
+// the frontend keeps the categories of the tags in use, it gets them again whenever they change
+fn emit_tag_categories(window: &tauri::Window, dataset: &Dataset) {
+    let _ = window.emit("tag_categories_updated", dataset.tag_categories()).map_err(|err| Logger::error(&format!("Error sending tag categories to main window: {}", err)));
+}
+
+// `category` None makes the tag general again
+#[tauri::command]
+pub fn set_tag_category(tag: String, category: Option<TagCategory>, state: State<DatasetState>, window: tauri::Window) -> bool {
+    let mut dataset = state.dataset.lock().unwrap();
+    if let Some(dataset) = &mut *dataset {
+        Arc::make_mut(&mut dataset.categories).set(&tag, category);
+        emit_tag_categories(&window, dataset);
+        match dataset.categories.save(Path::new(&dataset.path)) {
+            Ok(_) => true,
+            Err(err) => {
+                Logger::error(&format!("Could not save tag categories: {}", err));
+                false
+            }
+        }
+    } else {
+        Logger::error(&format!("Could not set the category of tag '{}': dataset is None", tag));
+        false
+    }
+}
+
+// imports the categories from a CSV tag dump, see `TagCategories::import_csv`. returns how many tags got a category.
+#[tauri::command]
+pub fn import_tag_categories(path: String, state: State<DatasetState>, window: tauri::Window) -> Result<usize, String> {
+    let contents = match read_to_string(&path) {
+        Ok(contents) => contents,
+        Err(err) => {
+            Logger::error(&format!("Could not read tag categories from '{}': {}", path, err));
+            return Err(err.to_string());
+        }
+    };
+
+    let mut dataset = state.dataset.lock().unwrap();
+    if let Some(dataset) = &mut *dataset {
+        let imported = Arc::make_mut(&mut dataset.categories).import_csv(&contents);
+        if let Err(err) = dataset.categories.save(Path::new(&dataset.path)) {
+            Logger::error(&format!("Could not save tag categories: {}", err));
+            return Err(err.to_string());
+        }
+
+        emit_tag_categories(&window, dataset);
+        Logger::info(&format!("Imported the categories of {} tags from '{}'", imported, path));
+        Ok(imported)
+    } else {
+        Logger::error(&format!("Could not import tag categories from '{}': dataset is None", path));
+        Err("No dataset is open".to_string())
+    }
+}
+
+// for tags the frontend doesn't know the category of yet, like a tag the user just typed
+#[tauri::command]
+pub fn get_tag_categories(tags: Vec<String>, state: State<DatasetState>) -> HashMap<String, TagCategory> {
+    let dataset = state.dataset.lock().unwrap();
+    match &*dataset {
+        Some(dataset) => tags.into_iter().map(|tag| {
+            let category = dataset.categories.get(&tag);
+            (tag, category)
+        }).collect(),
+        None => HashMap::new()
+    }
+}
@@ -0,0 +1,181 @@
+use std::path::Path;
+
+use tauri::State;
+
+use dtm_core::{conflict::{CaptionConflict, ConflictResolution}, history::TagOperation, logger::Logger, pinned::PinnedTagViolation, source::CaptionSource, stats::{DatasetStats, DEFAULT_CO_OCCURRENCE_TAGS}};
+use crate::menu::file::load_dataset;
+use crate::state::{self, DatasetState};
+
+
+#[tauri::command]
+pub fn dataset_has_unsaved_changes(state: State<DatasetState>) -> bool {
+    state.has_unsaved_changes()
+}
+
+#[tauri::command]
+pub fn get_dirty_dataset_images(state: State<DatasetState>) -> Vec<String> {
+    state.dirty_images()
+}
+
+
+#[tauri::command]
+pub fn get_caption_conflicts(state: State<DatasetState>) -> Vec<CaptionConflict> {
+    let dataset = state.dataset.lock().unwrap();
+    match &*dataset {
+        Some(dataset) => dataset.caption_conflicts(None),
+        None => Vec::new()
+    }
+}
+
+// `tags` are the tags the frontend tried to save, when the conflict came from a single image edit
+#[tauri::command]
+pub fn resolve_caption_conflict(relative_path: String, resolution: ConflictResolution, tags: Option<Vec<String>>, state: State<DatasetState>, window: tauri::Window) -> bool {
+    let mut dataset = state.dataset.lock().unwrap();
+    if let Some(dataset) = &mut *dataset {
+        match dataset.resolve_conflict(&relative_path, resolution, tags) {
+            Ok(new) => {
+                state.record_history(TagOperation::Replace, dataset, &new);
+                *dataset = new;
+
+                // taking theirs for an image that was deleted removes it
+                if !dataset.data.iter().any(|image| image.relative_path == relative_path) {
+                    let _ = window.emit("image_removed", &relative_path).map_err(|err| Logger::error(&format!("Error sending removed image to main window: {}", err)));
+                }
+                state::emit_updated_images(&window, dataset, std::slice::from_ref(&relative_path));
+                state::sync_dirty_state(&window, dataset);
+                Logger::info(&format!("Resolved caption conflict for image '{}' with {:?}", relative_path, resolution));
+                true
+            },
+            Err(err) => {
+                Logger::error(&format!("Could not resolve caption conflict for image '{}': {}", relative_path, err));
+                false
+            }
+        }
+    } else {
+        Logger::error(&format!("Could not resolve caption conflict for image '{}': dataset is None", relative_path));
+        false
+    }
+}
+// names of the images matching the query, see `dtm_core::query` for the syntax
+#[tauri::command]
+pub fn query_images(query: String, state: State<DatasetState>) -> Result<Vec<String>, String> {
+    let dataset = state.dataset.lock().unwrap();
+    match &*dataset {
+        Some(dataset) => dataset.query_images(&query).map_err(|err| {
+            Logger::warn(&format!("Could not run query '{}': {}", query, err));
+            err.to_string()
+        }),
+        None => Err("No dataset is open".to_string())
+    }
+}
+
+// `top_n` is the number of most used tags in the co-occurrence matrix
+#[tauri::command]
+pub fn get_dataset_stats(top_n: Option<usize>, state: State<DatasetState>) -> Result<DatasetStats, String> {
+    let dataset = state.dataset.lock().unwrap();
+    match &*dataset {
+        Some(dataset) => Ok(dataset.stats(top_n.unwrap_or(DEFAULT_CO_OCCURRENCE_TAGS))),
+        None => Err("No dataset is open".to_string())
+    }
+}
+
+// saves the pinned tags and puts them in front on every image right away, so the images that broke the rule
+// show up as unsaved. returns those images as they were before.
+#[tauri::command]
+pub fn set_pinned_tags(tags: Vec<String>, state: State<DatasetState>, window: tauri::Window) -> Result<Vec<PinnedTagViolation>, String> {
+    let mut dataset = state.dataset.lock().unwrap();
+    if let Some(dataset) = &mut *dataset {
+        dataset.settings.pinned_tags = tags.iter().map(|tag| tag.trim().to_string()).filter(|tag| !tag.is_empty()).collect();
+        if let Err(err) = dataset.settings.save(Path::new(&dataset.path)) {
+            Logger::error(&format!("Could not save pinned tags: {}", err));
+            return Err(err.to_string());
+        }
+
+        let violations = dataset.pinned_tag_violations();
+        let indices: Vec<usize> = (0..dataset.data.len()).collect();
+        let (new, _) = dataset.enforce_pinned_tags(&indices);
+        state.record_history(TagOperation::Replace, dataset, &new);
+        *dataset = new;
+
+        let relative_paths: Vec<String> = violations.iter().map(|violation| violation.relative_path.clone()).collect();
+        state::emit_updated_images(&window, dataset, &relative_paths);
+        state::sync_dirty_state(&window, dataset);
+        Logger::info(&format!("Set pinned tags {:?}, {} images didn't start with them", dataset.settings.pinned_tags, violations.len()));
+        Ok(violations)
+    } else {
+        Logger::error("Could not set pinned tags: dataset is None");
+        Err("No dataset is open".to_string())
+    }
+}
+
+// images that don't start with the pinned tags, e.g. after their captions were changed outside the app. they get fixed on the next save
+#[tauri::command]
+pub fn get_pinned_tag_violations(state: State<DatasetState>) -> Vec<PinnedTagViolation> {
+    let dataset = state.dataset.lock().unwrap();
+    match &*dataset {
+        Some(dataset) => dataset.pinned_tag_violations(),
+        None => Vec::new()
+    }
+}
+
+// Declares where the captions of the open dataset are, None to detect it, and reopens the dataset to read them from there.
+// Unsaved edits would be lost, so they have to be saved first.
+#[tauri::command]
+pub fn set_caption_source(source: Option<CaptionSource>, state: State<DatasetState>, window: tauri::Window) -> Result<(), String> {
+    let dataset_path = {
+        let mut dataset = state.dataset.lock().unwrap();
+        let dataset = match &mut *dataset {
+            Some(dataset) => dataset,
+            None => {
+                Logger::error("Could not set caption source: dataset is None");
+                return Err("No dataset is open".to_string());
+            }
+        };
+        if dataset.has_unsaved_changes() {
+            return Err("Save the dataset before changing where its captions are".to_string());
+        }
+
+        dataset.settings.caption_source = source;
+        if let Err(err) = dataset.settings.save(Path::new(&dataset.path)) {
+            Logger::error(&format!("Could not save caption source: {}", err));
+            return Err(err.to_string());
+        }
+        dataset.path.clone()
+    };
+
+    // reopening takes the dataset lock itself
+    Logger::info(&format!("Set caption source to {:?}", source));
+    load_dataset(&window, Path::new(&dataset_path));
+    Ok(())
+}
+
+// Sets how many levels of subfolders the images of the open dataset are read from, None for the default,
+// and reopens the dataset with them. Like with the caption source, unsaved edits have to be saved first.
+#[tauri::command]
+pub fn set_max_depth(max_depth: Option<usize>, state: State<DatasetState>, window: tauri::Window) -> Result<(), String> {
+    let dataset_path = {
+        let mut dataset = state.dataset.lock().unwrap();
+        let dataset = match &mut *dataset {
+            Some(dataset) => dataset,
+            None => {
+                Logger::error("Could not set max depth: dataset is None");
+                return Err("No dataset is open".to_string());
+            }
+        };
+        if dataset.has_unsaved_changes() {
+            return Err("Save the dataset before changing which folders it is read from".to_string());
+        }
+
+        dataset.settings.max_depth = max_depth;
+        if let Err(err) = dataset.settings.save(Path::new(&dataset.path)) {
+            Logger::error(&format!("Could not save max depth: {}", err));
+            return Err(err.to_string());
+        }
+        dataset.path.clone()
+    };
+
+    // reopening takes the dataset lock itself, and restarts the watcher with the new depth
+    Logger::info(&format!("Set max depth to {:?}", max_depth));
+    load_dataset(&window, Path::new(&dataset_path));
+    Ok(())
+}
@@ -0,0 +1,48 @@
+use std::path::Path;
+
+use tauri::State;
+
+use dtm_core::{huggingface::{import_huggingface, HuggingFaceExportOptions, ImageTransfer}, logger::Logger};
+use crate::state::DatasetState;
+
+
+// Exports the open dataset to `output` as an imagefolder with a `metadata.jsonl`, see `dtm_core::huggingface`.
+// Returns how many images were exported. Copying the images takes a while, so the command is async and runs off the main thread.
+#[tauri::command]
+pub async fn export_huggingface_dataset(output: String, options: Option<HuggingFaceExportOptions>, state: State<'_, DatasetState>) -> Result<usize, String> {
+    // the lock is only held to clone the dataset, the export works on the clone
+    let dataset = match &*state.dataset.lock().unwrap() {
+        Some(dataset) => dataset.clone(),
+        None => {
+            Logger::error(&format!("Could not export to '{}': dataset is None", output));
+            return Err("No dataset is open".to_string());
+        }
+    };
+
+    match dataset.export_huggingface(Path::new(&output), &options.unwrap_or_default()) {
+        Ok(exported) => {
+            Logger::info(&format!("Exported {} images to '{}'", exported, output));
+            Ok(exported)
+        },
+        Err(err) => {
+            Logger::error(&format!("Could not export to '{}': {}", output, err));
+            Err(err.to_string())
+        }
+    }
+}
+
+// Writes a caption file for every image in the `metadata.jsonl` of `source`, copying the images to `destination` unless it is `source`.
+// Returns how many images were imported, the imported folder can be opened as a dataset afterwards.
+#[tauri::command]
+pub async fn import_huggingface_dataset(source: String, destination: String, transfer: Option<ImageTransfer>) -> Result<usize, String> {
+    match import_huggingface(Path::new(&source), Path::new(&destination), transfer.unwrap_or(ImageTransfer::Copy)) {
+        Ok(imported) => {
+            Logger::info(&format!("Imported {} images from '{}' to '{}'", imported, source, destination));
+            Ok(imported)
+        },
+        Err(err) => {
+            Logger::error(&format!("Could not import from '{}': {}", source, err));
+            Err(err.to_string())
+        }
+    }
+}
@@ -0,0 +1,69 @@
+use std::path::Path;
+
+use tauri::State;
+
+use dtm_core::{kohya::{KohyaConfigOptions, KohyaSubset, CONFIG_FILE}, logger::Logger};
+use crate::state::DatasetState;
+
+
+#[tauri::command]
+pub fn get_kohya_subsets(state: State<DatasetState>) -> Vec<KohyaSubset> {
+    let dataset = state.dataset.lock().unwrap();
+    match &*dataset {
+        Some(dataset) => dataset.kohya_subsets(),
+        None => Vec::new()
+    }
+}
+
+// Saves the repeats of the subset in `folder`, None goes back to what the folder name says. Returns every subset.
+#[tauri::command]
+pub fn set_kohya_repeats(folder: String, repeats: Option<u32>, state: State<DatasetState>) -> Result<Vec<KohyaSubset>, String> {
+    let mut dataset = state.dataset.lock().unwrap();
+    if let Some(dataset) = &mut *dataset {
+        match repeats {
+            Some(0) => return Err("A subset has to be repeated at least once".to_string()),
+            Some(repeats) => dataset.settings.repeats.insert(folder.clone(), repeats),
+            None => dataset.settings.repeats.remove(&folder)
+        };
+        if let Err(err) = dataset.settings.save(Path::new(&dataset.path)) {
+            Logger::error(&format!("Could not save repeats: {}", err));
+            return Err(err.to_string());
+        }
+
+        Logger::info(&format!("Set the repeats of '{}' to {:?}", folder, repeats));
+        Ok(dataset.kohya_subsets())
+    } else {
+        Logger::error("Could not set repeats: dataset is None");
+        Err("No dataset is open".to_string())
+    }
+}
+
+// Writes the `dataset_config.toml` of the open dataset to `output`, or to the dataset root. Returns where it was written.
+#[tauri::command]
+pub fn export_kohya_config(output: Option<String>, options: Option<KohyaConfigOptions>, state: State<DatasetState>) -> Result<String, String> {
+    let dataset = state.dataset.lock().unwrap();
+    let dataset = match &*dataset {
+        Some(dataset) => dataset,
+        None => {
+            Logger::error("Could not export the Kohya config: dataset is None");
+            return Err("No dataset is open".to_string());
+        }
+    };
+
+    // the config describes the caption files on disk, unsaved edits wouldn't make it into training
+    if dataset.has_unsaved_changes() {
+        return Err("Save the dataset before exporting its Kohya config".to_string());
+    }
+
+    let output = output.unwrap_or_else(|| Path::new(&dataset.path).join(CONFIG_FILE).to_string_lossy().to_string());
+    match dataset.write_kohya_config(Path::new(&output), &options.unwrap_or_default()) {
+        Ok(_) => {
+            Logger::info(&format!("Wrote the Kohya config to '{}'", output));
+            Ok(output)
+        },
+        Err(err) => {
+            Logger::error(&format!("Could not export the Kohya config to '{}': {}", output, err));
+            Err(err.to_string())
+        }
+    }
+}
@@ -1 +1,10 @@
-pub mod tags;
\ No newline at end of file
+pub mod backup;
+pub mod category;
+pub mod dataset;
+pub mod huggingface;
+pub mod kohya;
+pub mod normalize;
+pub mod rules;
+pub mod tags;
+pub mod vocabulary;
+pub mod webdataset;
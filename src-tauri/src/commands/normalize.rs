@@ -0,0 +1,48 @@
+use std::path::Path;
+
+use tauri::State;
+
+use dtm_core::{history::{ImageTagChange, TagOperation}, logger::Logger, normalize::NormalizeOptions};
+use crate::state::{self, DatasetState};
+
+
+#[tauri::command]
+pub fn set_normalize_options(options: NormalizeOptions, state: State<DatasetState>) -> bool {
+    let mut dataset = state.dataset.lock().unwrap();
+    if let Some(dataset) = &mut *dataset {
+        dataset.settings.normalization = options;
+        match dataset.settings.save(Path::new(&dataset.path)) {
+            Ok(_) => true,
+            Err(err) => {
+                Logger::error(&format!("Could not save normalize options: {}", err));
+                false
+            }
+        }
+    } else {
+        Logger::error("Could not set normalize options: dataset is None");
+        false
+    }
+}
+
+// normalizes every image with the dataset's options and returns what changed per image.
+// with `dry_run` nothing is changed, the report shows what would change.
+#[tauri::command]
+pub fn normalize_dataset_tags(dry_run: bool, state: State<DatasetState>, window: tauri::Window) -> Result<Vec<ImageTagChange>, String> {
+    let mut dataset = state.dataset.lock().unwrap();
+    if let Some(dataset) = &mut *dataset {
+        let (new, changes) = dataset.normalize_tags(&dataset.settings.normalization, dry_run);
+        if !dry_run {
+            state.record_history(TagOperation::Replace, dataset, &new);
+            *dataset = new;
+
+            let relative_paths: Vec<String> = changes.iter().map(|change| change.relative_path.clone()).collect();
+            state::emit_updated_images(&window, dataset, &relative_paths);
+            state::sync_dirty_state(&window, dataset);
+        }
+        Logger::info(&format!("Normalized dataset tags{}, {} images changed", if dry_run { " (dry run)" } else { "" }, changes.len()));
+        Ok(changes)
+    } else {
+        Logger::error("Could not normalize dataset tags: dataset is None");
+        Err("No dataset is open".to_string())
+    }
+}
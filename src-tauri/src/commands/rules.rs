@@ -0,0 +1,64 @@
+use std::path::Path;
+
+use tauri::State;
+
+use dtm_core::{history::{ImageTagChange, TagOperation}, logger::Logger, rules::{TagRule, TagRules}};
+use crate::state::{self, DatasetState};
+
+
+#[tauri::command]
+pub fn get_dataset_rules(state: State<DatasetState>) -> Result<Vec<TagRule>, String> {
+    let dataset = state.dataset.lock().unwrap();
+    match &*dataset {
+        Some(dataset) => TagRules::load(Path::new(&dataset.path)).map(|rules| rules.rules).map_err(|err| err.to_string()),
+        None => Err("No dataset is open".to_string())
+    }
+}
+
+#[tauri::command]
+pub fn set_dataset_rules(rules: Vec<TagRule>, state: State<DatasetState>) -> bool {
+    let dataset = state.dataset.lock().unwrap();
+    if let Some(dataset) = &*dataset {
+        match (TagRules { rules }).save(Path::new(&dataset.path)) {
+            Ok(_) => true,
+            Err(err) => {
+                Logger::error(&format!("Could not save dataset rules: {}", err));
+                false
+            }
+        }
+    } else {
+        Logger::error("Could not set dataset rules: dataset is None");
+        false
+    }
+}
+
+// applies the rules file to every image and returns what changed per image.
+// with `dry_run` nothing is changed, the report shows what would change.
+#[tauri::command]
+pub fn apply_dataset_rules(dry_run: bool, state: State<DatasetState>, window: tauri::Window) -> Result<Vec<ImageTagChange>, String> {
+    let mut dataset = state.dataset.lock().unwrap();
+    if let Some(dataset) = &mut *dataset {
+        let rules = match TagRules::load(Path::new(&dataset.path)) {
+            Ok(rules) => rules,
+            Err(err) => {
+                Logger::error(&format!("Could not load dataset rules: {}", err));
+                return Err(err.to_string());
+            }
+        };
+
+        let (new, changes) = dataset.apply_rules(&rules, dry_run);
+        if !dry_run {
+            state.record_history(TagOperation::Replace, dataset, &new);
+            *dataset = new;
+
+            let relative_paths: Vec<String> = changes.iter().map(|change| change.relative_path.clone()).collect();
+            state::emit_updated_images(&window, dataset, &relative_paths);
+            state::sync_dirty_state(&window, dataset);
+        }
+        Logger::info(&format!("Applied dataset rules{}, {} images changed", if dry_run { " (dry run)" } else { "" }, changes.len()));
+        Ok(changes)
+    } else {
+        Logger::error("Could not apply dataset rules: dataset is None");
+        Err("No dataset is open".to_string())
+    }
+}
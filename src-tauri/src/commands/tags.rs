@@ -1,45 +1,69 @@
 use tauri::State;
 
-use crate::{utils::{dataset::DatasetImage, logger::Logger}, state::DatasetState};
+use dtm_core::{bulk::BulkTagOperation, dataset::{DatasetErrorType, DatasetImage}, history::TagOperation, logger::Logger, sort::SortStrategy, weight::WeightedTag};
+use crate::state::{self, DatasetState};
 
 
 #[tauri::command]
-pub fn save_dataset_image_tags(image: DatasetImage, state: State<DatasetState>, window: tauri::Window) -> bool {
+pub fn save_dataset_image_tags(image: DatasetImage, state: State<DatasetState>, window: tauri::Window) -> Result<DatasetImage, String> {
     let mut dataset = state.dataset.lock().unwrap();
     if let Some(dataset) = &mut *dataset {
         let mut clone = image.clone();
+        let before_tags = dataset.data.iter().find(|dataset_image| dataset_image.relative_path == image.relative_path).map(|dataset_image| dataset_image.tags.clone()).unwrap_or_default();
         match dataset.update_image(&mut clone) {
             Ok(new) => {
+                state.record_history(TagOperation::between(&before_tags, &image.tags), dataset, &new);
                 *dataset = new;
 
-                // now that we know the dataset is updated, we want to enable the save button
-                // if it is currently disabled
-                let _ = window.menu_handle().get_item("save_dataset").set_enabled(false).map_err(|err| Logger::error(&format!("Error disabling save menu item: {}", err)));
-                Logger::info(&format!("Saved image tags for image '{}'", image.name));
-                true
+                // the pinned tags were put in front of what the frontend sent us
+                if clone.tags != image.tags {
+                    state::emit_updated_images(&window, dataset, std::slice::from_ref(&image.relative_path));
+                }
+                // now that we know the dataset is updated, the save menu item and title follow its dirty state
+                state::sync_dirty_state(&window, dataset);
+                Logger::info(&format!("Saved image tags for image '{}'", image.relative_path));
+                // the image as it is now, pinned tags included, for the frontend to show instead of what it sent
+                Ok(clone)
             },
             Err(err) => {
-                Logger::error(&format!("Could not save image tags for image '{}': {}", image.name, err));
-                false
+                // the caption file changed on disk, the frontend lets the user pick whose tags to keep
+                if let DatasetErrorType::Conflict(_) = err.type_ {
+                    let _ = window.emit("dataset_conflicts", dataset.caption_conflicts(Some(&image))).map_err(|err| Logger::error(&format!("Error sending conflicts to main window: {}", err)));
+                }
+                Logger::error(&format!("Could not save image tags for image '{}': {}", image.relative_path, err));
+                Err(err.to_string())
             }
         }
     } else {
-        Logger::error(&format!("Could not save image tags for image '{}': dataset is None", image.name));
-        false
+        Logger::error(&format!("Could not save image tags for image '{}': dataset is None", image.relative_path));
+        Err("No dataset is open".to_string())
     }
 }
 
 #[tauri::command]
-pub fn delete_dataset_image_tag(tag: String, image_name: String, state: State<DatasetState>) -> bool {
+pub fn delete_dataset_image_tag(tag: String, relative_path: String, state: State<DatasetState>, window: tauri::Window) -> bool {
     let mut dataset = state.dataset.lock().unwrap();
     if let Some(dataset) = &mut *dataset {
-        match dataset.delete_image_tag(tag.clone(), image_name) {
+        match dataset.delete_image_tag(tag.clone(), relative_path.clone()) {
             Ok(new) => {
+                state.record_history(TagOperation::Delete, dataset, &new);
                 *dataset = new;
+                state::sync_dirty_state(&window, dataset);
                 Logger::info(&format!("Deleted image tag '{}'", tag));
                 true
             },
             Err(err) => {
+                // the caption file changed on disk, our side of the conflict is the image without the tag
+                if let DatasetErrorType::Conflict(_) = err.type_ {
+                    let mine = dataset.data.iter().find(|image| image.relative_path == relative_path).map(|image| {
+                        let mut image = image.clone();
+                        if let Some(index) = image.tags.iter().position(|image_tag| image_tag == &tag) {
+                            image.tags.remove(index);
+                        }
+                        image
+                    });
+                    let _ = window.emit("dataset_conflicts", dataset.caption_conflicts(mine.as_ref())).map_err(|err| Logger::error(&format!("Error sending conflicts to main window: {}", err)));
+                }
                 Logger::error(&format!("Could not delete image tag '{}': {}", tag, err));
                 false
             }
@@ -48,4 +72,115 @@ pub fn delete_dataset_image_tag(tag: String, image_name: String, state: State<Da
         Logger::error("Could not delete image tag: dataset is None");
         false
     }
-}
\ No newline at end of file
+}
+
+#[tauri::command]
+pub fn undo_tag_edit(state: State<DatasetState>, window: tauri::Window) -> bool {
+    state::undo_tag_edit(&window, &state)
+}
+
+#[tauri::command]
+pub fn redo_tag_edit(state: State<DatasetState>, window: tauri::Window) -> bool {
+    state::redo_tag_edit(&window, &state)
+}
+// returns how many images had the tag
+#[tauri::command]
+pub fn rename_dataset_tag(old_tag: String, new_tag: String, state: State<DatasetState>, window: tauri::Window) -> Result<usize, String> {
+    let mut dataset = state.dataset.lock().unwrap();
+    if let Some(dataset) = &mut *dataset {
+        match dataset.rename_tag(&old_tag, &new_tag) {
+            Ok((new, changed_images)) => {
+                state.record_history(TagOperation::Replace, dataset, &new);
+                *dataset = new;
+
+                state::emit_updated_images(&window, dataset, &changed_images);
+                state::sync_dirty_state(&window, dataset);
+                Logger::info(&format!("Renamed tag '{}' to '{}' in {} images", old_tag, new_tag, changed_images.len()));
+                Ok(changed_images.len())
+            },
+            Err(err) => {
+                Logger::error(&format!("Could not rename tag '{}' to '{}': {}", old_tag, new_tag, err));
+                Err(err.to_string())
+            }
+        }
+    } else {
+        Logger::error(&format!("Could not rename tag '{}': dataset is None", old_tag));
+        Err("No dataset is open".to_string())
+    }
+}
+
+// returns how many images changed
+#[tauri::command]
+pub fn bulk_edit_dataset_tags(relative_paths: Vec<String>, operation: BulkTagOperation, state: State<DatasetState>, window: tauri::Window) -> Result<usize, String> {
+    let mut dataset = state.dataset.lock().unwrap();
+    if let Some(dataset) = &mut *dataset {
+        match dataset.bulk_edit_tags(Some(&relative_paths), &operation) {
+            Ok((new, changed_images)) => {
+                state.record_history(operation.history_operation(), dataset, &new);
+                *dataset = new;
+
+                state::emit_updated_images(&window, dataset, &changed_images);
+                state::sync_dirty_state(&window, dataset);
+                Logger::info(&format!("Applied {:?} to {} of {} images", operation, changed_images.len(), relative_paths.len()));
+                Ok(changed_images.len())
+            },
+            Err(err) => {
+                Logger::error(&format!("Could not apply {:?}: {}", operation, err));
+                Err(err.to_string())
+            }
+        }
+    } else {
+        Logger::error(&format!("Could not apply {:?}: dataset is None", operation));
+        Err("No dataset is open".to_string())
+    }
+}
+
+// `weight` None removes the emphasis from the tag
+#[tauri::command]
+pub fn set_dataset_tag_weight(relative_path: String, tag: String, weight: Option<f64>, state: State<DatasetState>, window: tauri::Window) -> bool {
+    let mut dataset = state.dataset.lock().unwrap();
+    if let Some(dataset) = &mut *dataset {
+        match dataset.set_tag_weight(&relative_path, &tag, weight) {
+            Ok(new) => {
+                state.record_history(TagOperation::Replace, dataset, &new);
+                *dataset = new;
+
+                state::emit_updated_images(&window, dataset, std::slice::from_ref(&relative_path));
+                state::sync_dirty_state(&window, dataset);
+                Logger::info(&format!("Set the weight of tag '{}' on image '{}' to {:?}", tag, relative_path, weight));
+                true
+            },
+            Err(err) => {
+                Logger::error(&format!("Could not set the weight of tag '{}' on image '{}': {}", tag, relative_path, err));
+                false
+            }
+        }
+    } else {
+        Logger::error(&format!("Could not set the weight of tag '{}' on image '{}': dataset is None", tag, relative_path));
+        false
+    }
+}
+
+#[tauri::command]
+pub fn parse_weighted_tag(tag: String) -> WeightedTag {
+    WeightedTag::parse(&tag)
+}
+
+// sorts every image when `relative_paths` is None, returns how many images changed
+#[tauri::command]
+pub fn sort_dataset_tags(relative_paths: Option<Vec<String>>, strategy: SortStrategy, state: State<DatasetState>, window: tauri::Window) -> Result<usize, String> {
+    let mut dataset = state.dataset.lock().unwrap();
+    if let Some(dataset) = &mut *dataset {
+        let (new, changed_images) = dataset.sort_tags(relative_paths.as_deref(), &strategy);
+        state.record_history(TagOperation::Reorder, dataset, &new);
+        *dataset = new;
+
+        state::emit_updated_images(&window, dataset, &changed_images);
+        state::sync_dirty_state(&window, dataset);
+        Logger::info(&format!("Sorted the tags of {} images with {:?}", changed_images.len(), strategy));
+        Ok(changed_images.len())
+    } else {
+        Logger::error(&format!("Could not sort tags with {:?}: dataset is None", strategy));
+        Err("No dataset is open".to_string())
+    }
+}
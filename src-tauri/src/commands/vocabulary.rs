@@ -0,0 +1,38 @@
+use std::path::Path;
+
+use tauri::State;
+
+use dtm_core::{logger::Logger, vocabulary::{autocomplete, TagSuggestion, TagVocabulary, DEFAULT_SUGGESTION_LIMIT}};
+use crate::state::DatasetState;
+
+
+// loads the vocabulary to autocomplete from, see `TagVocabulary::parse` for the formats.
+// the open dataset remembers it, so it is loaded again with the dataset. returns how many tags it has.
+#[tauri::command]
+pub fn load_tag_vocabulary(path: String, state: State<DatasetState>) -> Result<usize, String> {
+    let vocabulary = match TagVocabulary::load(Path::new(&path)) {
+        Ok(vocabulary) => vocabulary,
+        Err(err) => {
+            Logger::error(&format!("Could not load tag vocabulary from '{}': {}", path, err));
+            return Err(err.to_string());
+        }
+    };
+    let tag_count = vocabulary.entries.len();
+
+    let mut dataset = state.dataset.lock().unwrap();
+    if let Some(dataset) = &mut *dataset {
+        dataset.settings.vocabulary_path = Some(path.clone());
+        let _ = dataset.settings.save(Path::new(&dataset.path)).map_err(|err| Logger::error(&format!("Could not save vocabulary path: {}", err)));
+    }
+    *state.vocabulary.lock().unwrap() = vocabulary;
+
+    Logger::info(&format!("Loaded a tag vocabulary of {} tags from '{}'", tag_count, path));
+    Ok(tag_count)
+}
+
+#[tauri::command]
+pub fn autocomplete_tag(prefix: String, limit: Option<usize>, state: State<DatasetState>) -> Vec<TagSuggestion> {
+    let dataset = state.dataset.lock().unwrap();
+    let vocabulary = state.vocabulary.lock().unwrap();
+    autocomplete(&vocabulary, dataset.as_ref(), &prefix, limit.unwrap_or(DEFAULT_SUGGESTION_LIMIT))
+}
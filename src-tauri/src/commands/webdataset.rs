@@ -0,0 +1,32 @@
+use std::path::Path;
+
+use tauri::State;
+
+use dtm_core::{logger::Logger, webdataset::{ShardIndex, WebDatasetExportOptions}};
+use crate::state::DatasetState;
+
+
+// Packs the open dataset into tar shards in `output`, see `dtm_core::webdataset`. Returns the shard index that was written.
+// Packing the images takes a while, so the command is async and runs off the main thread.
+#[tauri::command]
+pub async fn export_webdataset(output: String, options: Option<WebDatasetExportOptions>, state: State<'_, DatasetState>) -> Result<ShardIndex, String> {
+    // the lock is only held to clone the dataset, the export works on the clone
+    let dataset = match &*state.dataset.lock().unwrap() {
+        Some(dataset) => dataset.clone(),
+        None => {
+            Logger::error(&format!("Could not export to '{}': dataset is None", output));
+            return Err("No dataset is open".to_string());
+        }
+    };
+
+    match dataset.export_webdataset(Path::new(&output), &options.unwrap_or_default()) {
+        Ok(index) => {
+            Logger::info(&format!("Exported {} images in {} shards to '{}'", index.samples(), index.shardlist.len(), output));
+            Ok(index)
+        },
+        Err(err) => {
+            Logger::error(&format!("Could not export to '{}': {}", output, err));
+            Err(err.to_string())
+        }
+    }
+}
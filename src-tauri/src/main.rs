@@ -3,8 +3,10 @@
 
 use std::sync::Mutex;
 
+use dtm_core::history::TagHistory;
+use dtm_core::vocabulary::TagVocabulary;
+
 mod commands;
-mod utils;
 mod menu;
 mod state;
 
@@ -14,8 +16,47 @@ fn main() {
 
     tauri::Builder::default()
         .plugin(tauri_plugin_persisted_scope::init())
-        .manage(state::DatasetState { dataset: Mutex::new(None) })
-        .invoke_handler(tauri::generate_handler![commands::tags::save_dataset_image_tags, commands::tags::delete_dataset_image_tag])
+        .manage(state::DatasetState { dataset: Mutex::new(None), history: Mutex::new(TagHistory::default()), watcher: Mutex::new(None), vocabulary: Mutex::new(TagVocabulary::default()) })
+        .invoke_handler(tauri::generate_handler![
+            commands::tags::save_dataset_image_tags,
+            commands::tags::delete_dataset_image_tag,
+            commands::tags::undo_tag_edit,
+            commands::tags::redo_tag_edit,
+            commands::tags::rename_dataset_tag,
+            commands::tags::bulk_edit_dataset_tags,
+            commands::tags::set_dataset_tag_weight,
+            commands::tags::parse_weighted_tag,
+            commands::tags::sort_dataset_tags,
+            commands::dataset::dataset_has_unsaved_changes,
+            commands::dataset::get_dirty_dataset_images,
+            commands::dataset::get_caption_conflicts,
+            commands::dataset::resolve_caption_conflict,
+            commands::dataset::query_images,
+            commands::dataset::get_dataset_stats,
+            commands::dataset::set_pinned_tags,
+            commands::dataset::get_pinned_tag_violations,
+            commands::dataset::set_caption_source,
+            commands::dataset::set_max_depth,
+            commands::backup::list_dataset_backups,
+            commands::backup::restore_dataset_backup,
+            commands::backup::set_backup_retention,
+            commands::rules::get_dataset_rules,
+            commands::rules::set_dataset_rules,
+            commands::rules::apply_dataset_rules,
+            commands::normalize::set_normalize_options,
+            commands::normalize::normalize_dataset_tags,
+            commands::category::set_tag_category,
+            commands::category::import_tag_categories,
+            commands::category::get_tag_categories,
+            commands::vocabulary::load_tag_vocabulary,
+            commands::vocabulary::autocomplete_tag,
+            commands::huggingface::export_huggingface_dataset,
+            commands::huggingface::import_huggingface_dataset,
+            commands::kohya::get_kohya_subsets,
+            commands::kohya::set_kohya_repeats,
+            commands::kohya::export_kohya_config,
+            commands::webdataset::export_webdataset
+        ])
         .menu(app_menu)
         .on_menu_event(menu::app_menu_event_handler)
         .run(tauri::generate_context!())
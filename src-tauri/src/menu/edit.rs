@@ -0,0 +1,30 @@
+use tauri::Manager;
+use tauri::{Submenu, CustomMenuItem, Menu, MenuItem, Window};
+
+use crate::state;
+
+pub fn get_edit_submenu() -> Submenu {
+    let undo_item = CustomMenuItem::new("undo_tag_edit".to_string(), "Undo").accelerator("Cmd+z").into();
+    let redo_item = CustomMenuItem::new("redo_tag_edit".to_string(), "Redo").accelerator("Cmd+Shift+z").into();
+
+    // the native clipboard items keep copy & paste working in the tag inputs
+    Submenu::new("Edit", Menu::with_items([
+        undo_item,
+        redo_item,
+        MenuItem::Separator.into(),
+        MenuItem::Cut.into(),
+        MenuItem::Copy.into(),
+        MenuItem::Paste.into(),
+        MenuItem::SelectAll.into(),
+    ]))
+}
+
+pub fn undo_handler(main_window: &Window) {
+    let app = main_window.app_handle();
+    state::undo_tag_edit(main_window, &app.state::<state::DatasetState>());
+}
+
+pub fn redo_handler(main_window: &Window) {
+    let app = main_window.app_handle();
+    state::redo_tag_edit(main_window, &app.state::<state::DatasetState>());
+}
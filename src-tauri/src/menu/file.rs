@@ -1,15 +1,33 @@
+use std::path::Path;
+
 use tauri::Manager;
 use tauri::{Submenu, CustomMenuItem, Menu, api::dialog, Window};
 
 use crate::state;
-use crate::utils::logger::Logger;
-use crate::utils::dataset::Dataset;
+use dtm_core::logger::Logger;
+use dtm_core::backup::list_snapshots;
+use dtm_core::dataset::{Dataset, DatasetErrorType};
+use dtm_core::history::TagOperation;
+use dtm_core::huggingface::{import_huggingface, HuggingFaceExportOptions, ImageTransfer};
+use dtm_core::kohya::{KohyaConfigOptions, CONFIG_FILE};
+use dtm_core::rules::TagRules;
+use dtm_core::settings::DatasetSettings;
+use dtm_core::vocabulary::TagVocabulary;
+use dtm_core::webdataset::WebDatasetExportOptions;
 
 pub fn get_file_submenu() -> Submenu {
     let open_item = CustomMenuItem::new("open_dataset".to_string(), "Open Dataset...").accelerator("Cmd+o").into();
     let save_item = CustomMenuItem::new("save_dataset".to_string(), "Save Dataset...").accelerator("Cmd+s").disabled().into();
+    let restore_item = CustomMenuItem::new("restore_backup".to_string(), "Restore from Backup...").into();
+    let repeats_item = CustomMenuItem::new("kohya_repeats".to_string(), "Kohya Repeats...").into();
+    let import_item = CustomMenuItem::new("import_huggingface".to_string(), "Import metadata.jsonl...").into();
+    let export_submenu = Submenu::new("Export", Menu::new()
+        .add_item(CustomMenuItem::new("export_huggingface".to_string(), "Hugging Face (metadata.jsonl)..."))
+        .add_item(CustomMenuItem::new("export_webdataset".to_string(), "WebDataset (tar shards)..."))
+        .add_item(CustomMenuItem::new("export_kohya".to_string(), "Kohya (dataset_config.toml)..."))
+    ).into();
 
-    Submenu::new("File", Menu::with_items([open_item, save_item]))
+    Submenu::new("File", Menu::with_items([open_item, save_item, restore_item, repeats_item, import_item, export_submenu]))
 }
 
 pub fn open_dataset_handler(main_window: &Window) {
@@ -19,59 +37,270 @@ pub fn open_dataset_handler(main_window: &Window) {
         // The only way for path_buf to be None is if the user canceled the dialog.
         // We can just ignore it in that case.
         if let Some(buf) = path_buf {
-            let path = buf.as_path();
-
-            // we want to now load the dataset from the path
-            let dataset = match Dataset::from_path(path) {
-                Ok(dataset) => dataset,
-                Err(err) => {
-                    // if the dataset is an error, we want to show an error dialog to the user and return
-                    dialog::message(Some(&window), "Error loading Dataset", format!("An error occurred while loading the Dataset. Please try again.\n\n{}", err));
-                    return;
-                }
-            };
-
-            // if the dataset was successfully loaded, we want to do a couple things:
-            // 1. pass the dataset to the main window
-            let _ = window.emit("dataset_loaded", dataset.clone()).map_err(|err| Logger::error(&format!("Error sending dataset to main window: {}", err)));
-            // 2. set the window title to the name of the dataset
-            let _ = window.set_title(dataset.name.as_str()).map_err(|err| Logger::error(&format!("Error setting window title: {}", err)));
-            // TODO: do we want to enable the save menu right away? or only after the user has made changes?
-            // It's a good question, since we technically make changes to the dataset when we load it, since we trim the tags.
-            // For now, we'll keep enabling it right away.
-            // 3. enable the save menu item
-            let _ = window.menu_handle().get_item("save_dataset").set_enabled(true).map_err(|err| Logger::error(&format!("Error enabling save menu item: {}", err)));
-
-            // now that we've done all that, we want to set the dataset in the app state
-            let _ = window.app_handle().state::<state::DatasetState>().dataset.lock().map(|mut dataset_state| {
-                *dataset_state = Some(dataset);
-            }).map_err(|err| Logger::error(&format!("Error setting dataset in app state: {}", err)));
+            load_dataset(&window, &buf);
         }
     });
 }
 
-pub fn save_dataset_handler(main_window: &Window, dataset: Option<&Dataset>) {
-    if dataset.is_none() {
-        // if the dataset is None, we want to show an error dialog to the user and return
-        dialog::message(Some(main_window), "Error saving Dataset", "An error occurred while saving the Dataset. Please try again.");
-        return;
+// loads the dataset at `path` into the app, replacing the dataset that was open
+pub fn load_dataset(window: &Window, path: &Path) {
+    // we want to now load the dataset from the path, as deep into its subfolders as its settings say
+    let max_depth = DatasetSettings::load(path).max_depth();
+    let dataset = match Dataset::from_path(path, max_depth, None) {
+        Ok(dataset) => dataset,
+        Err(err) => {
+            // if the dataset is an error, we want to show an error dialog to the user and return
+            dialog::message(Some(window), "Error loading Dataset", format!("An error occurred while loading the Dataset. Please try again.\n\n{}", err));
+            return;
+        }
+    };
+
+    // the dataset's tags are normalized and its rules applied right away.
+    // the changed images show up as unsaved, so nothing is written until the user saves.
+    let loaded = dataset.clone();
+    let (dataset, changes) = dataset.normalize_tags(&dataset.settings.normalization, false);
+    if !changes.is_empty() {
+        Logger::info(&format!("Normalized the tags of {} images", changes.len()));
     }
+    let dataset = match TagRules::load(path) {
+        Ok(rules) => {
+            let (dataset, changes) = dataset.apply_rules(&rules, false);
+            if !changes.is_empty() {
+                Logger::info(&format!("Dataset rules changed the tags of {} images", changes.len()));
+            }
+            dataset
+        },
+        Err(err) => {
+            dialog::message(Some(window), "Error loading Dataset rules", format!("The rules file of the Dataset could not be read, so no rules were applied.\n\n{}", err));
+            dataset
+        }
+    };
+
+    // if the dataset was successfully loaded, we want to do a couple things:
+    // 1. pass the dataset to the main window
+    let _ = window.emit("dataset_loaded", dataset.clone()).map_err(|err| Logger::error(&format!("Error sending dataset to main window: {}", err)));
+    // 2. set the window title to the name of the dataset, and the save menu item to its dirty state.
+    // a freshly loaded dataset only has unsaved changes if the rules changed something, otherwise saving stays disabled until the user edits something
+    state::sync_dirty_state(window, &dataset);
+
+    // now that we've done all that, we want to set the dataset in the app state.
+    // edits made to the previous dataset can't be undone anymore, and its watcher stops
+    let app = window.app_handle();
+    let app_state = app.state::<state::DatasetState>();
+    let vocabulary_path = dataset.settings.vocabulary_path.clone();
+    let caption_source = dataset.caption_source;
+    app_state.clear_history();
+    // changes made by normalizing and the rules can be undone like any other edit
+    app_state.record_history(TagOperation::Replace, &loaded, &dataset);
+    let _ = app_state.dataset.lock().map(|mut dataset_state| {
+        *dataset_state = Some(dataset);
+    }).map_err(|err| Logger::error(&format!("Error setting dataset in app state: {}", err)));
+    // the dataset remembers the vocabulary it was last autocompleted from. without one, the previous dataset's goes away too
+    let vocabulary = match &vocabulary_path {
+        Some(vocabulary_path) => match TagVocabulary::load(Path::new(vocabulary_path)) {
+            Ok(vocabulary) => vocabulary,
+            Err(err) => {
+                Logger::error(&format!("Error loading tag vocabulary from '{}': {}", vocabulary_path, err));
+                TagVocabulary::default()
+            }
+        },
+        None => TagVocabulary::default()
+    };
+    let _ = app_state.vocabulary.lock().map(|mut vocabulary_state| *vocabulary_state = vocabulary).map_err(|err| Logger::error(&format!("Error setting tag vocabulary in app state: {}", err)));
+    let _ = app_state.watcher.lock().map(|mut watcher_state| {
+        *watcher_state = Some(state::watcher::watch(window.clone(), path.to_path_buf(), max_depth, caption_source));
+    }).map_err(|err| Logger::error(&format!("Error watching dataset: {}", err)));
+}
+
+// Saves the dataset in the app state. It stays locked until the saved dataset is back in the state,
+// so changes the watcher or a command make in the meantime wait for the save instead of being overwritten by it.
+pub fn save_dataset_handler(main_window: &Window) {
+    let app = main_window.app_handle();
+    let app_state = app.state::<state::DatasetState>();
+    let mut dataset_state = match app_state.dataset.lock() {
+        Ok(dataset_state) => dataset_state,
+        Err(err) => {
+            Logger::error(&format!("Error locking dataset in app state: {}", err));
+            return;
+        }
+    };
+    let dataset = match &mut *dataset_state {
+        Some(dataset) => dataset,
+        None => {
+            // if the dataset is None, we want to show an error dialog to the user and return
+            dialog::message(Some(main_window), "Error saving Dataset", "An error occurred while saving the Dataset. Please try again.");
+            return;
+        }
+    };
+
+    match dataset.save_image_tags() {
+        Ok(saved) => {
+            // saving puts the pinned tags in front, the frontend needs the images that got reordered
+            let reordered: Vec<String> = dataset.data.iter().zip(saved.data.iter())
+                .filter(|(before, after)| before.tags != after.tags)
+                .map(|(_, after)| after.relative_path.clone())
+                .collect();
+            if !reordered.is_empty() {
+                Logger::info(&format!("Put the pinned tags in front on {} images", reordered.len()));
+                state::emit_updated_images(main_window, &saved, &reordered);
+            }
 
-    // we know the dataset is Some, so we can unwrap it
-    match dataset.unwrap().save_image_tags() {
-        Ok(dataset) => {
             // if the dataset was successfully saved, we want to do a couple things:
-            // 1. update the dataset in the app state
-            let _ = main_window.app_handle().state::<state::DatasetState>().dataset.lock().map(|mut dataset_state| {
-                *dataset_state = Some(dataset);
-            }).map_err(|err| Logger::error(&format!("Error setting dataset in app state: {}", err)));
+            // 1. sync the save menu item and window title, which disables saving again until the user makes changes
+            state::sync_dirty_state(main_window, &saved);
+            // 2. update the dataset in the app state
+            *dataset = saved;
+            drop(dataset_state);
 
             dialog::message(Some(main_window), "Dataset Saved", "The Dataset was successfully saved.");
-            // Now that we've saved the dataset, we want to disable the save menu item again, until the user makes changes
-            let _ = main_window.menu_handle().get_item("save_dataset").set_enabled(false).map_err(|err| Logger::error(&format!("Error disabling save menu item: {}", err)));
         },
         Err(err) => {
+            // the frontend lets the user pick whose tags to keep for every conflicting caption
+            if let DatasetErrorType::Conflict(_) = err.type_ {
+                let _ = main_window.emit("dataset_conflicts", dataset.caption_conflicts(None)).map_err(|err| Logger::error(&format!("Error sending conflicts to main window: {}", err)));
+            }
+            drop(dataset_state);
             dialog::message(Some(main_window), "Error Saving Dataset", format!("An error occurred while saving the Dataset. Please try again.\n\n{}", err));
         }
     }
-}
\ No newline at end of file
+}
+
+pub fn restore_backup_handler(main_window: &Window, dataset: Option<&Dataset>) {
+    let dataset = match dataset {
+        Some(dataset) => dataset,
+        None => {
+            dialog::message(Some(main_window), "No Dataset Open", "Open a Dataset before restoring it from a backup.");
+            return;
+        }
+    };
+
+    let snapshots = list_snapshots(Path::new(&dataset.path));
+    if snapshots.is_empty() {
+        dialog::message(Some(main_window), "No Backups", "There are no backups for this Dataset yet. A backup is taken every time the Dataset is saved.");
+        return;
+    }
+
+    // the frontend lets the user pick a snapshot and the images to restore, and calls `restore_dataset_backup`
+    let _ = main_window.emit("dataset_backups", snapshots).map_err(|err| Logger::error(&format!("Error sending backups to main window: {}", err)));
+}
+
+pub fn export_huggingface_handler(main_window: &Window, dataset: Option<&Dataset>) {
+    let dataset = match dataset {
+        Some(dataset) => dataset.clone(),
+        None => {
+            dialog::message(Some(main_window), "No Dataset Open", "Open a Dataset before exporting it.");
+            return;
+        }
+    };
+
+    let window = main_window.clone();
+    dialog::FileDialogBuilder::default().set_title("Export to folder").pick_folder(move |path_buf| {
+        if let Some(buf) = path_buf {
+            match dataset.export_huggingface(&buf, &HuggingFaceExportOptions::default()) {
+                Ok(exported) => {
+                    Logger::info(&format!("Exported {} images to '{}'", exported, buf.to_string_lossy()));
+                    dialog::message(Some(&window), "Dataset Exported", format!("Exported {} images with their metadata.jsonl to '{}'.", exported, buf.to_string_lossy()));
+                },
+                Err(err) => {
+                    dialog::message(Some(&window), "Error Exporting Dataset", format!("An error occurred while exporting the Dataset.\n\n{}", err));
+                }
+            }
+        }
+    });
+}
+
+pub fn export_webdataset_handler(main_window: &Window, dataset: Option<&Dataset>) {
+    let dataset = match dataset {
+        Some(dataset) => dataset.clone(),
+        None => {
+            dialog::message(Some(main_window), "No Dataset Open", "Open a Dataset before exporting it.");
+            return;
+        }
+    };
+
+    let window = main_window.clone();
+    dialog::FileDialogBuilder::default().set_title("Export shards to folder").pick_folder(move |path_buf| {
+        if let Some(buf) = path_buf {
+            match dataset.export_webdataset(&buf, &WebDatasetExportOptions::default()) {
+                Ok(index) => {
+                    Logger::info(&format!("Exported {} images in {} shards to '{}'", index.samples(), index.shardlist.len(), buf.to_string_lossy()));
+                    dialog::message(Some(&window), "Dataset Exported", format!("Exported {} images in {} shards to '{}'.", index.samples(), index.shardlist.len(), buf.to_string_lossy()));
+                },
+                Err(err) => {
+                    dialog::message(Some(&window), "Error Exporting Dataset", format!("An error occurred while exporting the Dataset.\n\n{}", err));
+                }
+            }
+        }
+    });
+}
+
+pub fn kohya_repeats_handler(main_window: &Window, dataset: Option<&Dataset>) {
+    let dataset = match dataset {
+        Some(dataset) => dataset,
+        None => {
+            dialog::message(Some(main_window), "No Dataset Open", "Open a Dataset before setting its repeats.");
+            return;
+        }
+    };
+
+    // the frontend lets the user change the repeats of every subset, and calls `set_kohya_repeats`
+    let _ = main_window.emit("kohya_subsets", dataset.kohya_subsets()).map_err(|err| Logger::error(&format!("Error sending Kohya subsets to main window: {}", err)));
+}
+
+pub fn export_kohya_handler(main_window: &Window, dataset: Option<&Dataset>) {
+    let dataset = match dataset {
+        Some(dataset) => dataset.clone(),
+        None => {
+            dialog::message(Some(main_window), "No Dataset Open", "Open a Dataset before exporting it.");
+            return;
+        }
+    };
+    // the config describes the caption files on disk, unsaved edits wouldn't make it into training
+    if dataset.has_unsaved_changes() {
+        dialog::message(Some(main_window), "Unsaved Changes", "Save the Dataset before exporting its Kohya config, the trainer reads the captions that are on disk.");
+        return;
+    }
+
+    let window = main_window.clone();
+    dialog::FileDialogBuilder::default().set_title("Save Kohya config").set_directory(&dataset.path).set_file_name(CONFIG_FILE).save_file(move |path_buf| {
+        if let Some(buf) = path_buf {
+            match dataset.write_kohya_config(&buf, &KohyaConfigOptions::default()) {
+                Ok(_) => {
+                    Logger::info(&format!("Wrote the Kohya config to '{}'", buf.to_string_lossy()));
+                    dialog::message(Some(&window), "Kohya Config Exported", format!("Wrote the config of {} subsets to '{}'.", dataset.kohya_subsets().len(), buf.to_string_lossy()));
+                },
+                Err(err) => {
+                    dialog::message(Some(&window), "Error Exporting Kohya Config", format!("An error occurred while exporting the Kohya config.\n\n{}", err));
+                }
+            }
+        }
+    });
+}
+
+// Writes a caption file next to every image of a folder with a `metadata.jsonl`, and opens the folder as the dataset.
+pub fn import_huggingface_handler(main_window: &Window) {
+    let window = main_window.clone();
+    dialog::FileDialogBuilder::default().set_title("Folder with a metadata.jsonl").pick_folder(move |path_buf| {
+        let buf = match path_buf {
+            Some(buf) => buf,
+            None => return
+        };
+
+        let message = "The captions in metadata.jsonl are written to a caption file next to every image, replacing the caption files that are already there.";
+        let dialog_window = window.clone();
+        dialog::ask(Some(&dialog_window), "Import metadata.jsonl", message, move |import| {
+            if !import {
+                return;
+            }
+            match import_huggingface(&buf, &buf, ImageTransfer::Copy) {
+                Ok(imported) => {
+                    Logger::info(&format!("Imported the captions of {} images from '{}'", imported, buf.to_string_lossy()));
+                    load_dataset(&window, &buf);
+                },
+                Err(err) => {
+                    dialog::message(Some(&window), "Error Importing Dataset", format!("An error occurred while importing metadata.jsonl.\n\n{}", err));
+                }
+            }
+        });
+    });
+}
@@ -1,17 +1,21 @@
-mod file;
+mod edit;
+pub mod file;
 mod named;
 
 use tauri::{ Menu, WindowMenuEvent, Manager };
 
-use crate::{utils::logger::Logger, state::DatasetState};
+use dtm_core::logger::Logger;
+use crate::state::DatasetState;
 
-use self::file::{open_dataset_handler, save_dataset_handler};
+use self::edit::{redo_handler, undo_handler};
+use self::file::{export_huggingface_handler, export_kohya_handler, export_webdataset_handler, import_huggingface_handler, kohya_repeats_handler, open_dataset_handler, restore_backup_handler, save_dataset_handler};
 
 pub fn new() -> Menu {
     let named_submenu = named::get_named_submenu();
     let file_submenu = file::get_file_submenu();
+    let edit_submenu = edit::get_edit_submenu();
 
-    Menu::new().add_submenu(named_submenu).add_submenu(file_submenu)
+    Menu::new().add_submenu(named_submenu).add_submenu(file_submenu).add_submenu(edit_submenu)
 }
 
 pub fn app_menu_event_handler(event: WindowMenuEvent) {
@@ -19,13 +23,21 @@ pub fn app_menu_event_handler(event: WindowMenuEvent) {
     let window = event.window();
     let app = window.app_handle();
     let state = app.state::<DatasetState>();
-    let locked_state = state.dataset.lock().expect("Could not lock dataset");
-    let dataset = locked_state.as_ref().clone();
-    // we want to get the app state so we can get the dataset to pass to the save handler
+    // we want to get the app state so we can get the dataset to pass to the handlers.
+    // the lock is released right away, saving takes it again for as long as it writes
+    let dataset = state.dataset.lock().expect("Could not lock dataset").clone();
     match event.menu_item_id() {
         // we pass the window to the handler so we can update the window title with the name of the dataset
         "open_dataset" => open_dataset_handler(window),
-        "save_dataset" => save_dataset_handler(window, dataset),
+        "save_dataset" => save_dataset_handler(window),
+        "restore_backup" => restore_backup_handler(window, dataset.as_ref()),
+        "kohya_repeats" => kohya_repeats_handler(window, dataset.as_ref()),
+        "import_huggingface" => import_huggingface_handler(window),
+        "export_huggingface" => export_huggingface_handler(window, dataset.as_ref()),
+        "export_webdataset" => export_webdataset_handler(window, dataset.as_ref()),
+        "export_kohya" => export_kohya_handler(window, dataset.as_ref()),
+        "undo_tag_edit" => undo_handler(window),
+        "redo_tag_edit" => redo_handler(window),
         _ => {
             // error if none of the above passes
             Logger::debug(&format!("tauri event {:?}", event))
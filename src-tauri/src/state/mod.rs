@@ -1,7 +1,106 @@
+pub mod watcher;
+
 use std::sync::Mutex;
 
-use crate::utils::dataset::Dataset;
+use tauri::Window;
+
+use dtm_core::{dataset::Dataset, history::{HistoryEntry, TagHistory, TagOperation}, logger::Logger, vocabulary::TagVocabulary};
 
+// when more than one is needed, `dataset` is always locked first
 pub struct DatasetState {
-    pub dataset: Mutex<Option<Dataset>>
-}
\ No newline at end of file
+    pub dataset: Mutex<Option<Dataset>>,
+    pub history: Mutex<TagHistory>,
+    pub watcher: Mutex<Option<watcher::DatasetWatcher>>,
+    pub vocabulary: Mutex<TagVocabulary>
+}
+
+impl DatasetState {
+    pub fn has_unsaved_changes(&self) -> bool {
+        match self.dataset.lock() {
+            Ok(dataset) => dataset.as_ref().map(|dataset| dataset.has_unsaved_changes()).unwrap_or(false),
+            Err(_) => false
+        }
+    }
+
+    pub fn dirty_images(&self) -> Vec<String> {
+        match self.dataset.lock() {
+            Ok(dataset) => dataset.as_ref().map(|dataset| dataset.dirty_images()).unwrap_or_default(),
+            Err(_) => Vec::new()
+        }
+    }
+
+    // records the tag changes between the two datasets as one undoable step
+    pub fn record_history(&self, operation: TagOperation, before: &Dataset, after: &Dataset) {
+        if let Some(entry) = HistoryEntry::between(operation, before, after) {
+            let _ = self.history.lock().map(|mut history| history.record(entry)).map_err(|err| Logger::error(&format!("Error recording tag history: {}", err)));
+        }
+    }
+
+    pub fn clear_history(&self) {
+        let _ = self.history.lock().map(|mut history| history.clear()).map_err(|err| Logger::error(&format!("Error clearing tag history: {}", err)));
+    }
+}
+
+// tells the frontend about images whose tags the backend changed on its own
+pub fn emit_updated_images(window: &Window, dataset: &Dataset, relative_paths: &[String]) {
+    let images: Vec<_> = dataset.data.iter().filter(|image| relative_paths.contains(&image.relative_path)).cloned().collect();
+    let _ = window.emit("dataset_images_updated", images).map_err(|err| Logger::error(&format!("Error sending updated images to main window: {}", err)));
+}
+
+pub fn undo_tag_edit(window: &Window, state: &DatasetState) -> bool {
+    step_tag_history(window, state, true)
+}
+
+pub fn redo_tag_edit(window: &Window, state: &DatasetState) -> bool {
+    step_tag_history(window, state, false)
+}
+
+// undo and redo only change the tags in our state, the caption files are written on the next save
+fn step_tag_history(window: &Window, state: &DatasetState, undo: bool) -> bool {
+    let mut dataset = match state.dataset.lock() {
+        Ok(dataset) => dataset,
+        Err(err) => {
+            Logger::error(&format!("Error locking dataset: {}", err));
+            return false;
+        }
+    };
+    let dataset = match &mut *dataset {
+        Some(dataset) => dataset,
+        None => return false
+    };
+
+    let mut history = match state.history.lock() {
+        Ok(history) => history,
+        Err(err) => {
+            Logger::error(&format!("Error locking tag history: {}", err));
+            return false;
+        }
+    };
+
+    let result = if undo { history.undo(dataset) } else { history.redo(dataset) };
+    match result {
+        Some((operation, images)) => {
+            Logger::info(&format!("{} tag operation {:?} on {} images", if undo { "Undid" } else { "Redid" }, operation, images.len()));
+            let relative_paths: Vec<String> = images.into_iter().map(|image| image.relative_path).collect();
+            emit_updated_images(window, dataset, &relative_paths);
+            sync_dirty_state(window, dataset);
+            true
+        },
+        None => false
+    }
+}
+
+// Brings the window in line with the dirty state of the dataset:
+// the save menu item is only enabled while there are unsaved changes,
+// the window title gets a '*' suffix, and the frontend is told which images are dirty.
+pub fn sync_dirty_state(window: &Window, dataset: &Dataset) {
+    let dirty_images = dataset.dirty_images();
+    let has_unsaved_changes = !dirty_images.is_empty();
+
+    let _ = window.menu_handle().get_item("save_dataset").set_enabled(has_unsaved_changes).map_err(|err| Logger::error(&format!("Error updating save menu item: {}", err)));
+
+    let title = if has_unsaved_changes { format!("{} *", dataset.name) } else { dataset.name.clone() };
+    let _ = window.set_title(title.as_str()).map_err(|err| Logger::error(&format!("Error setting window title: {}", err)));
+
+    let _ = window.emit("dataset_dirty_images", dirty_images).map_err(|err| Logger::error(&format!("Error sending dirty images to main window: {}", err)));
+}
@@ -0,0 +1,106 @@
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+use tauri::{Manager, Window};
+
+use dtm_core::{dataset::DatasetImage, logger::Logger, source::CaptionSource, watcher::{apply_change, DatasetScan, WatcherEvent}};
+
+use super::{sync_dirty_state, DatasetState};
+
+// how often the dataset folder is scanned for changes. every scan that finds nothing doubles the wait
+// up to `MAX_POLL_INTERVAL`, a change brings it back down
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+const MAX_POLL_INTERVAL: Duration = Duration::from_secs(16);
+// a scan of a big dataset takes a while, the watcher waits this many times as long before the next one
+const SCAN_COST_FACTOR: u32 = 10;
+
+#[derive(Serialize, Clone)]
+struct ImageRenamedPayload {
+    from: String,
+    image: DatasetImage,
+}
+
+// Polls the open dataset folder and keeps the dataset in the app state in line with what happens on disk.
+// The folder is scanned and compared without the dataset lock, which is only taken to apply what changed.
+// The watcher thread stops once this is dropped, which happens when another dataset is opened.
+pub struct DatasetWatcher {
+    stop: Arc<AtomicBool>,
+}
+
+impl Drop for DatasetWatcher {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
+
+pub fn watch(window: Window, dataset_path: PathBuf, max_depth: usize, caption_source: CaptionSource) -> DatasetWatcher {
+    let stop = Arc::new(AtomicBool::new(false));
+    let thread_stop = stop.clone();
+
+    // the first scan happens right away, so nothing that changes after the dataset was loaded gets missed
+    let mut scan = DatasetScan::new(&dataset_path, max_depth, caption_source).unwrap_or_default();
+
+    thread::spawn(move || {
+        let mut interval = POLL_INTERVAL;
+        loop {
+            thread::sleep(interval);
+            if thread_stop.load(Ordering::Relaxed) {
+                break;
+            }
+
+            let started = Instant::now();
+            let next = match DatasetScan::new(&dataset_path, max_depth, caption_source) {
+                Ok(next) => next,
+                Err(err) => {
+                    Logger::warn(&format!("Could not scan dataset for changes: {}", err));
+                    interval = (interval * 2).min(MAX_POLL_INTERVAL);
+                    continue;
+                }
+            };
+            let changes = scan.changes(&next);
+            scan = next;
+
+            let scan_cost = started.elapsed() * SCAN_COST_FACTOR;
+            if changes.is_empty() {
+                interval = (interval * 2).min(MAX_POLL_INTERVAL).max(scan_cost);
+                continue;
+            }
+            interval = POLL_INTERVAL.max(scan_cost);
+
+            let app = window.app_handle();
+            let state = app.state::<DatasetState>();
+            let mut dataset = match state.dataset.lock() {
+                Ok(dataset) => dataset,
+                Err(_) => break
+            };
+            // another dataset may have been opened while we were scanning, its changes are none of our business
+            if thread_stop.load(Ordering::Relaxed) {
+                break;
+            }
+            let dataset = match &mut *dataset {
+                Some(dataset) if Path::new(&dataset.path) == dataset_path => dataset,
+                _ => break
+            };
+
+            for change in &changes {
+                let result = match apply_change(dataset, change) {
+                    Some(WatcherEvent::ImageAdded(image)) => window.emit("image_added", image),
+                    Some(WatcherEvent::ImageRemoved(name)) => window.emit("image_removed", name),
+                    Some(WatcherEvent::ImageRenamed { from, image }) => window.emit("image_renamed", ImageRenamedPayload { from, image }),
+                    Some(WatcherEvent::TagsChangedExternally(image)) => window.emit("tags_changed_externally", image),
+                    Some(WatcherEvent::Conflict(conflict)) => window.emit("dataset_conflicts", vec![conflict]),
+                    None => Ok(())
+                };
+                let _ = result.map_err(|err| Logger::error(&format!("Error sending dataset change to main window: {}", err)));
+            }
+
+            sync_dirty_state(&window, dataset);
+        }
+    });
+
+    DatasetWatcher { stop }
+}
@@ -1,3 +0,0 @@
-pub mod dataset;
-pub mod file;
-pub mod logger;
\ No newline at end of file
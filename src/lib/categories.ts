@@ -0,0 +1,25 @@
+import type { TagCategory } from '$lib/types';
+
+// The order tag groups are shown in, same as the backend sorts them
+export const tagCategoryOrder: TagCategory[] = [
+	'character',
+	'artist',
+	'style',
+	'general',
+	'quality',
+	'meta'
+];
+
+// Tailwind classes for the left border of a tag
+export const tagCategoryColours: Record<TagCategory, string> = {
+	character: 'border-green-400',
+	artist: 'border-red-400',
+	style: 'border-purple-400',
+	general: 'border-zinc-400',
+	quality: 'border-yellow-400',
+	meta: 'border-orange-400'
+};
+
+export function tagCategory(categories: Record<string, TagCategory>, tag: string): TagCategory {
+	return categories[tag] ?? 'general';
+}
@@ -1,18 +1,24 @@
-import type { Dataset } from '$lib/types';
+import type { Dataset, TagCategory } from '$lib/types';
 import { writable, derived } from 'svelte/store';
 
 // Main store
 const datasetStore = writable<Dataset | null>(null);
 
-// Active dataset image, used for the tags viewer
+// Names of the images with unsaved tag changes, sent by the backend
+export const dirtyDatasetImagesStore = writable<string[]>([]);
+
+// Category of every tag in use, sent by the backend. Tags missing from it are general
+export const tagCategoriesStore = writable<Record<string, TagCategory>>({});
+
+// Active dataset image by its relative path, used for the tags viewer
 export const activeDatasetImageStore = writable<string | null>(null);
 
 // Active dataset tags, derived from the active dataset image and the dataset stores
 export const activeDatasetTagsStore = derived(
 	[datasetStore, activeDatasetImageStore],
-	([$dataset, $imageName]) => {
-		if (!$dataset || !$imageName) return [] as string[];
-		const datasetImage = $dataset.data.find((img) => img.name === $imageName);
+	([$dataset, $relativePath]) => {
+		if (!$dataset || !$relativePath) return [] as string[];
+		const datasetImage = $dataset.data.find((img) => img.relative_path === $relativePath);
 		if (!datasetImage) {
 			console.log('literally how the hell did this happen????');
 			return [] as string[];
@@ -1,11 +1,152 @@
 export type DatasetImage = {
 	name: string;
 	path: string;
+	relative_path: string;
 	tags: string[];
 };
 
+export type CaptionFormat = 'comma' | 'period' | 'newline' | 'sentence';
+
+// where the captions of a dataset are, see `set_caption_source`
+export type CaptionSource = 'txt' | 'caption' | 'json' | 'metadata_jsonl' | 'metadata_csv';
+
 export type Dataset = {
 	name: string;
 	path: string;
+	caption_format: CaptionFormat;
+	caption_source: CaptionSource;
+	tag_categories: Record<string, TagCategory>;
 	data: DatasetImage[];
 };
+
+export type BackupFile = {
+	relative_path: string;
+	caption_path: string;
+	changed: boolean;
+};
+
+export type BackupSnapshot = {
+	id: string;
+	created_at: number;
+	files: BackupFile[];
+};
+
+export type CaptionConflict = {
+	relative_path: string;
+	caption_path: string;
+	mine: string[];
+	theirs: string[];
+};
+
+export type ConflictResolution = 'keep_mine' | 'take_theirs' | 'merge';
+
+export type TagPosition = 'prepend' | 'append' | { index: number };
+
+export type BulkTagOperation =
+	| { type: 'add'; tag: string; position: TagPosition; skip_existing: boolean }
+	| { type: 'remove'; tag: string }
+	| { type: 'replace'; old_tag: string; new_tag: string };
+
+export type TagCount = {
+	tag: string;
+	count: number;
+	share: number;
+};
+
+export type CoOccurrence = {
+	tags: string[];
+	counts: number[][];
+};
+
+export type DatasetStats = {
+	image_count: number;
+	tag_counts: TagCount[];
+	tags_per_image: number[];
+	co_occurrence: CoOccurrence;
+};
+
+export type TagRule =
+	| { type: 'alias'; from: string; to: string }
+	| { type: 'implication'; from: string; implies: string };
+
+export type ImageTagChange = {
+	relative_path: string;
+	before: string[];
+	after: string[];
+};
+
+export type NormalizeOptions = {
+	unicode_nfc: boolean;
+	lowercase: boolean;
+	word_separator: 'keep' | 'spaces' | 'underscores';
+	parentheses: 'keep' | 'strip' | 'escape';
+	collapse_whitespace: boolean;
+	dedupe: boolean;
+};
+
+export type WeightSyntax =
+	| { type: 'plain' }
+	| { type: 'explicit'; weight: string; before_colon: string; after_colon: string }
+	| { type: 'parentheses'; depth: number }
+	| { type: 'brackets'; depth: number };
+
+export type WeightedTag = {
+	text: string;
+	escaped: string;
+	weight: number | null;
+	syntax: WeightSyntax;
+};
+
+export type PinnedTagViolation = {
+	relative_path: string;
+	missing: string[];
+	leading: string[];
+};
+
+export type TagCategory = 'character' | 'artist' | 'style' | 'general' | 'quality' | 'meta';
+
+export type SortStrategy =
+	| { type: 'alphabetical' }
+	| { type: 'frequency' }
+	| { type: 'category' }
+	| { type: 'priority'; tags: string[] };
+
+export type TagSuggestion = {
+	tag: string;
+	category: TagCategory | null;
+	vocabulary_count: number;
+	dataset_count: number;
+	distance: number;
+};
+
+export type ImageTransfer = 'copy' | 'hard_link';
+
+export type HuggingFaceExportOptions = {
+	columns: 'text' | 'tags' | 'both';
+	transfer: ImageTransfer;
+};
+
+export type KohyaSubset = {
+	folder: string;
+	images: number;
+	repeats: number;
+	folder_repeats: number | null;
+};
+
+export type KohyaConfigOptions = {
+	resolution: number;
+	keep_tokens: number | null;
+};
+
+export type WebDatasetExportOptions = {
+	caption: 'txt' | 'json';
+	max_samples: number;
+	max_bytes: number | null;
+};
+
+export type ShardIndex = {
+	__kind__: string;
+	wids_version: number;
+	name: string;
+	shardlist: { url: string; nsamples: number }[];
+};